@@ -0,0 +1,127 @@
+use crate::image::Image;
+use crate::render::Tracer;
+use crate::sampling::Rng;
+use crate::shape::Hit;
+use crate::tonemap::{ToneMapper, ToneMapping};
+use crate::Vec3;
+
+/// Arbitrary output variable: an extra per-pixel buffer rendered alongside
+/// the colour. Each is accumulated with the same pixel filter as the colour,
+/// so IDs blend along object edges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aov {
+    /// Distance from the camera to the first hit; 0 where nothing is hit.
+    Depth,
+    /// World-space shading normal at the first hit.
+    Normal,
+    /// Diffuse or base colour of the first hit, textures applied.
+    Albedo,
+    /// Index of the first shape hit plus one; 0 for the background.
+    ObjectId,
+    /// Material index of the first hit plus one; 0 for the background.
+    MaterialId,
+    /// Light emitted by the first surface or reflected straight from the
+    /// lights, or the background seen directly.
+    Direct,
+    /// All remaining light; `direct + indirect` is the colour.
+    Indirect,
+}
+
+impl Aov {
+    pub fn from_name(name: &str) -> Option<Aov> {
+        match name {
+            "depth" => Some(Aov::Depth),
+            "normal" => Some(Aov::Normal),
+            "albedo" => Some(Aov::Albedo),
+            "object_id" => Some(Aov::ObjectId),
+            "material_id" => Some(Aov::MaterialId),
+            "direct" => Some(Aov::Direct),
+            "indirect" => Some(Aov::Indirect),
+            _ => None,
+        }
+    }
+
+    /// Name used for EXR layers and file name suffixes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    /// Maps an image of this AOV into `[0, 1]` for 8-bit output. Depth is
+    /// divided by the largest depth in the image, normals are stored as
+    /// `n * 0.5 + 0.5` and IDs become distinct colours, black for the
+    /// background. Albedo only gets the transfer function; direct and
+    /// indirect light are tone mapped like the colour.
+    pub fn to_display(&self, image: &Image, tone_mapping: &ToneMapping) -> Image {
+        let mut out = image.clone();
+        match self {
+            Aov::Depth => {
+                let max = image.pixels.iter().fold(0f32, |max, p| max.max(p.x));
+                if max > 0.0 {
+                    out.pixels.iter_mut().for_each(|p| *p /= max);
+                }
+            }
+            Aov::Normal => out
+                .pixels
+                .iter_mut()
+                .for_each(|p| *p = p.map(|v| v * 0.5 + 0.5)),
+            Aov::ObjectId | Aov::MaterialId => {
+                // Filtering blends IDs along edges; the nearest one wins.
+                out.pixels
+                    .iter_mut()
+                    .for_each(|p| *p = id_color(p.x.round().max(0.0) as u64));
+            }
+            Aov::Albedo => {
+                let transfer_only = ToneMapping {
+                    exposure: 0.0,
+                    mapper: ToneMapper::None,
+                    ..*tone_mapping
+                };
+                out = transfer_only.apply(image);
+            }
+            Aov::Direct | Aov::Indirect => out = tone_mapping.apply(image),
+        }
+        out
+    }
+}
+
+/// Bright pseudo-random colour for `id`, or black for 0.
+fn id_color(id: u64) -> Vec3 {
+    if id == 0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let mut rng = Rng::new(id, 0);
+    Vec3::new(rng.next_f32(), rng.next_f32(), rng.next_f32()).map(|v| 0.2 + 0.8 * v)
+}
+
+impl<'a> Tracer<'a> {
+    /// Value of `aov` for a camera ray whose first hit is `first` (shape index
+    /// and hit record), whose sampled colour is `color` and whose direct light,
+    /// from the same evaluation, is `direct`.
+    pub fn aov(&self, aov: Aov, first: Option<&(usize, Hit)>, color: &Vec3, direct: &Vec3) -> Vec3 {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let splat = |v: f32| Vec3::new(v, v, v);
+        match (aov, first) {
+            (Aov::Depth, Some((_, hit))) => splat(hit.t),
+            (Aov::Normal, Some((_, hit))) => hit.normal,
+            (Aov::Albedo, Some((_, hit))) => {
+                let material = self.material(hit);
+                material
+                    .pbr
+                    .map_or(material.diffuse_color, |pbr| pbr.base_color)
+            }
+            (Aov::ObjectId, Some((object, _))) => splat(*object as f32 + 1.0),
+            (Aov::MaterialId, Some((_, hit))) => splat(hit.material_id as f32 + 1.0),
+            (Aov::Direct, _) => *direct,
+            (Aov::Indirect, _) => color - direct,
+            (_, None) => zero,
+        }
+    }
+}
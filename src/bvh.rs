@@ -0,0 +1,396 @@
+use crate::Vec3;
+
+fn min3(a: &Vec3, b: &Vec3) -> Vec3 {
+    Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn max3(a: &Vec3, b: &Vec3) -> Vec3 {
+    Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: min3(&self.min, &other.min),
+            max: max3(&self.max, &other.max),
+        }
+    }
+
+    pub fn grow(&self, p: &Vec3) -> Aabb {
+        Aabb {
+            min: min3(&self.min, p),
+            max: max3(&self.max, p),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Slab test; returns the entry distance if the ray enters the box before `t_max`.
+    pub fn hit(&self, orig: &Vec3, inv_dir: &Vec3, t_max: f32) -> Option<f32> {
+        let mut t0 = 0f32;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let mut near = (self.min[axis] - orig[axis]) * inv_dir[axis];
+            let mut far = (self.max[axis] - orig[axis]) * inv_dir[axis];
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some(t0)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    /// First primitive for leaves, left child for interior nodes (the right
+    /// child always follows it).
+    start: usize,
+    /// Number of primitives; zero for interior nodes.
+    count: usize,
+}
+
+const BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Bounding volume hierarchy over primitive indices, built with a binned
+/// surface area heuristic. Primitive tests are supplied by the caller, so the
+/// same tree works for any kind of geometry.
+#[derive(Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: vec![],
+            indices: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            let centroids: Vec<Vec3> = bounds.iter().map(|b| b.centroid()).collect();
+            bvh.nodes.push(Node {
+                bounds: Aabb::empty(),
+                start: 0,
+                count: 0,
+            });
+            bvh.build_node(0, bounds, &centroids, 0, bounds.len());
+        }
+        bvh
+    }
+
+    fn build_node(
+        &mut self,
+        index: usize,
+        bounds: &[Aabb],
+        centroids: &[Vec3],
+        start: usize,
+        end: usize,
+    ) {
+        let mut node_bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for &i in &self.indices[start..end] {
+            node_bounds = node_bounds.union(&bounds[i]);
+            centroid_bounds = centroid_bounds.grow(&centroids[i]);
+        }
+        self.nodes[index] = Node {
+            bounds: node_bounds,
+            start,
+            count: end - start,
+        };
+        if end - start <= MAX_LEAF_SIZE {
+            return;
+        }
+        let (axis, position) = match self.find_split(
+            bounds,
+            centroids,
+            &node_bounds,
+            &centroid_bounds,
+            start,
+            end,
+        ) {
+            Some(split) => split,
+            None => return,
+        };
+
+        let mut mid = start;
+        for k in start..end {
+            if centroids[self.indices[k]][axis] < position {
+                self.indices.swap(k, mid);
+                mid += 1;
+            }
+        }
+        if mid == start || mid == end {
+            return;
+        }
+
+        let left = self.nodes.len();
+        self.nodes.push(self.nodes[index]);
+        self.nodes.push(self.nodes[index]);
+        self.nodes[index].start = left;
+        self.nodes[index].count = 0;
+        self.build_node(left, bounds, centroids, start, mid);
+        self.build_node(left + 1, bounds, centroids, mid, end);
+    }
+
+    /// Picks the axis and centroid position minimising the SAH cost, or `None`
+    /// when keeping the primitives in a single leaf is cheaper.
+    fn find_split(
+        &self,
+        bounds: &[Aabb],
+        centroids: &[Vec3],
+        node_bounds: &Aabb,
+        centroid_bounds: &Aabb,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, f32)> {
+        let mut best: Option<(usize, f32)> = None;
+        let mut best_cost = (end - start) as f32;
+        let parent_area = node_bounds.surface_area();
+        if parent_area <= 0.0 {
+            return None;
+        }
+
+        for axis in 0..3 {
+            let lo = centroid_bounds.min[axis];
+            let hi = centroid_bounds.max[axis];
+            if hi - lo <= 1e-6 {
+                continue;
+            }
+            let scale = BINS as f32 / (hi - lo);
+            let mut bin_bounds = [Aabb::empty(); BINS];
+            let mut bin_counts = [0usize; BINS];
+            for &i in &self.indices[start..end] {
+                let centroid = &centroids[i];
+                let b = (((centroid[axis] - lo) * scale) as usize).min(BINS - 1);
+                bin_bounds[b] = bin_bounds[b].union(&bounds[i]);
+                bin_counts[b] += 1;
+            }
+
+            let mut right_area = [0f32; BINS];
+            let mut right_count = [0usize; BINS];
+            let mut acc = Aabb::empty();
+            let mut count = 0;
+            for b in (1..BINS).rev() {
+                acc = acc.union(&bin_bounds[b]);
+                count += bin_counts[b];
+                right_area[b] = acc.surface_area();
+                right_count[b] = count;
+            }
+
+            let mut acc = Aabb::empty();
+            let mut count = 0;
+            for b in 1..BINS {
+                acc = acc.union(&bin_bounds[b - 1]);
+                count += bin_counts[b - 1];
+                let cost = 0.125
+                    + (acc.surface_area() * count as f32 + right_area[b] * right_count[b] as f32)
+                        / parent_area;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best = Some((axis, lo + b as f32 / scale));
+                }
+            }
+        }
+        best
+    }
+
+    /// Nearest hit along the ray. `hit(primitive, t_max)` must return the
+    /// distance to `primitive` if it is hit closer than `t_max`.
+    pub fn intersect<F>(
+        &self,
+        orig: &Vec3,
+        dir: &Vec3,
+        t_max: f32,
+        mut hit: F,
+    ) -> Option<(usize, f32)>
+    where
+        F: FnMut(usize, f32) -> Option<f32>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inv_dir = Vec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let mut closest: Option<(usize, f32)> = None;
+        let mut t_max = t_max;
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.hit(orig, &inv_dir, t_max).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for &i in &self.indices[node.start..node.start + node.count] {
+                    if let Some(t) = hit(i, t_max) {
+                        if t < t_max {
+                            t_max = t;
+                            closest = Some((i, t));
+                        }
+                    }
+                }
+                continue;
+            }
+            // Visit the nearer child first so later boxes can be culled.
+            let left = node.start;
+            let right = node.start + 1;
+            let t_left = self.nodes[left].bounds.hit(orig, &inv_dir, t_max);
+            let t_right = self.nodes[right].bounds.hit(orig, &inv_dir, t_max);
+            match (t_left, t_right) {
+                (Some(l), Some(r)) => {
+                    if l < r {
+                        stack.push(right);
+                        stack.push(left);
+                    } else {
+                        stack.push(left);
+                        stack.push(right);
+                    }
+                }
+                (Some(_), None) => stack.push(left),
+                (None, Some(_)) => stack.push(right),
+                (None, None) => {}
+            }
+        }
+        closest
+    }
+
+    /// True if any primitive blocks the ray before `t_max`; stops at the first hit.
+    pub fn occluded<F>(&self, orig: &Vec3, dir: &Vec3, t_max: f32, mut hit: F) -> bool
+    where
+        F: FnMut(usize, f32) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inv_dir = Vec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.hit(orig, &inv_dir, t_max).is_none() {
+                continue;
+            }
+            if node.count > 0 {
+                for &i in &self.indices[node.start..node.start + node.count] {
+                    if hit(i, t_max) {
+                        return true;
+                    }
+                }
+            } else {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampling::Rng;
+
+    fn random_vec(rng: &mut Rng, lo: f32, hi: f32) -> Vec3 {
+        let mut c = || lo + (hi - lo) * rng.next_f32();
+        Vec3::new(c(), c(), c())
+    }
+
+    /// Checks the tree against testing every box, for rays from outside the
+    /// scene towards random points inside it.
+    fn check_against_brute_force(boxes: &[Aabb], rng: &mut Rng) {
+        let bvh = Bvh::build(boxes);
+        let hit = |i: usize, orig: &Vec3, dir: &Vec3, t_max: f32| {
+            let inv_dir = Vec3::new(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+            boxes[i].hit(orig, &inv_dir, t_max)
+        };
+        for _ in 0..500 {
+            let orig = random_vec(rng, -1.0, 1.0).normalize() * 50.0;
+            let dir = (random_vec(rng, -8.0, 8.0) - orig).normalize();
+            let t_max = 30.0 + 60.0 * rng.next_f32();
+
+            let expected = (0..boxes.len())
+                .filter_map(|i| hit(i, &orig, &dir, t_max).map(|t| (i, t)))
+                .fold(None, |best: Option<(usize, f32)>, (i, t)| match best {
+                    Some((_, best_t)) if best_t <= t => best,
+                    _ => Some((i, t)),
+                });
+            let found = bvh.intersect(&orig, &dir, t_max, |i, t_max| hit(i, &orig, &dir, t_max));
+            assert_eq!(found.map(|(_, t)| t), expected.map(|(_, t)| t));
+            if let Some((i, t)) = found {
+                assert_eq!(hit(i, &orig, &dir, t_max), Some(t));
+            }
+
+            let occluded = bvh.occluded(&orig, &dir, t_max, |i, t_max| {
+                hit(i, &orig, &dir, t_max).is_some()
+            });
+            assert_eq!(occluded, expected.is_some());
+        }
+    }
+
+    #[test]
+    fn matches_brute_force_on_random_boxes() {
+        let mut rng = Rng::new(1, 0);
+        let boxes: Vec<Aabb> = (0..300)
+            .map(|_| {
+                let center = random_vec(&mut rng, -10.0, 10.0);
+                let half = random_vec(&mut rng, 0.05, 1.0);
+                Aabb {
+                    min: center - half,
+                    max: center + half,
+                }
+            })
+            .collect();
+        check_against_brute_force(&boxes, &mut rng);
+    }
+
+    #[test]
+    fn matches_brute_force_with_equal_centroids() {
+        let mut rng = Rng::new(2, 0);
+        let boxes: Vec<Aabb> = (0..40)
+            .map(|_| {
+                let half = random_vec(&mut rng, 0.1, 5.0);
+                Aabb {
+                    min: -half,
+                    max: half,
+                }
+            })
+            .collect();
+        check_against_brute_force(&boxes, &mut rng);
+    }
+
+    #[test]
+    fn empty_tree_hits_nothing() {
+        let bvh = Bvh::build(&[]);
+        let (orig, dir) = (Vec3::zeros(), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bvh.intersect(&orig, &dir, 1.0, |_, _| Some(0.5)).is_none());
+        assert!(!bvh.occluded(&orig, &dir, 1.0, |_, _| true));
+    }
+}
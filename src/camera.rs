@@ -0,0 +1,406 @@
+use crate::sampling::{uniform_disk, uniform_polygon};
+use crate::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// How image positions map to primary rays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// Parallel rays along the view direction; `size` is the extent of the
+    /// view along `fov_axis` in world units.
+    Orthographic {
+        size: f32,
+    },
+    /// Equidistant fisheye; `fov` is the angle across the inscribed image
+    /// circle along `fov_axis`.
+    Fisheye,
+    /// Full 360 x 180 degree latitude-longitude panorama; `fov` is ignored.
+    Equirectangular,
+}
+
+/// Which image axis the field of view spans.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FovAxis {
+    Vertical,
+    Horizontal,
+}
+
+/// Camera at `position` looking towards `target`. `up` only needs to be
+/// roughly perpendicular to the view direction; it is orthogonalised. A
+/// perspective camera with a non-zero `aperture` is a thin lens focused at
+/// `focus_distance`, otherwise a pinhole.
+#[derive(Clone, Copy)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Field of view in radians along `fov_axis`.
+    pub fov: f32,
+    pub fov_axis: FovAxis,
+    pub projection: Projection,
+    /// Lens radius in world units.
+    pub aperture: f32,
+    /// Distance along the view direction that is in sharp focus; defaults to
+    /// the distance to `target`.
+    pub focus_distance: Option<f32>,
+    /// Number of aperture blades, giving polygonal bokeh; a round aperture
+    /// when below 3.
+    pub blades: u32,
+    /// Rotation of the aperture polygon in radians.
+    pub blade_rotation: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            target: Vec3::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            fov: PI / 2.0,
+            fov_axis: FovAxis::Vertical,
+            projection: Projection::Perspective,
+            aperture: 0.0,
+            focus_distance: None,
+            blades: 0,
+            blade_rotation: 0.0,
+        }
+    }
+}
+
+impl Camera {
+    /// Camera at `position` looking at `target`, with a vertical field of view.
+    pub fn look_at(position: Vec3, target: Vec3, up: Vec3, fov: f32) -> Camera {
+        Camera {
+            position,
+            target,
+            up,
+            fov,
+            ..Camera::default()
+        }
+    }
+
+    pub fn focus_distance(&self) -> f32 {
+        self.focus_distance
+            .unwrap_or_else(|| (self.target - self.position).norm())
+    }
+
+    /// Checks that the camera describes a view, returning what is wrong
+    /// otherwise.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let distance = (self.target - self.position).norm();
+        if !(distance > 0.0 && distance.is_finite()) {
+            return Err("target must differ from position");
+        }
+        if !(self.fov > 0.0 && self.fov.is_finite()) {
+            return Err("fov must be positive");
+        }
+        match self.projection {
+            Projection::Perspective if self.fov >= PI => {
+                Err("perspective fov must be below 180 degrees")
+            }
+            Projection::Fisheye if self.fov > 2.0 * PI => {
+                Err("fisheye fov must not exceed 360 degrees")
+            }
+            Projection::Orthographic { size } if !(size > 0.0 && size.is_finite()) => {
+                Err("size must be positive")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Right, up and forward unit vectors of the view.
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = (self.target - self.position).normalize();
+        let mut right = forward.cross(&self.up);
+        if right.norm() < 1e-6 {
+            // `up` is parallel to the view direction; any perpendicular will do.
+            let axis = if forward.x.abs() < 0.9 {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else {
+                Vec3::new(0.0, 0.0, 1.0)
+            };
+            right = axis.cross(&forward);
+        }
+        let right = right.normalize();
+        let up = right.cross(&forward);
+        (right, up, forward)
+    }
+
+    /// Primary ray `(origin, direction)` through image position `(px, py)` of a
+    /// `width` x `height` image, where pixel `(i, j)` covers
+    /// `[i, i + 1) x [j, j + 1)`. `sample` in `[0, 1)^2` picks the point on the
+    /// lens. `None` for positions outside a fisheye's image circle.
+    pub fn generate_ray(
+        &self,
+        px: f32,
+        py: f32,
+        width: usize,
+        height: usize,
+        sample: Vec2,
+    ) -> Option<(Vec3, Vec3)> {
+        let width = width as f32;
+        let height = height as f32;
+        let sx = 2.0 * px / width - 1.0;
+        let sy = -(2.0 * py / height - 1.0);
+        let (right, up, forward) = self.basis();
+        match self.projection {
+            Projection::Perspective => {
+                let half = (self.fov / 2.0).tan();
+                let (x, y) = self.scale(sx, sy, half, width, height);
+                let dir = right * x + up * y + forward;
+                Some(self.thin_lens(dir, right, up, sample))
+            }
+            Projection::Orthographic { size } => {
+                let (x, y) = self.scale(sx, sy, size / 2.0, width, height);
+                Some((self.position + right * x + up * y, forward))
+            }
+            Projection::Fisheye => {
+                // Equidistant: the angle from the view axis grows linearly
+                // with the distance from the image centre.
+                let (x, y) = self.scale(sx, sy, 1.0, width, height);
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * self.fov / 2.0;
+                let (cos_phi, sin_phi) = if r > 0.0 { (x / r, y / r) } else { (1.0, 0.0) };
+                let dir = (right * cos_phi + up * sin_phi) * theta.sin() + forward * theta.cos();
+                Some((self.position, dir))
+            }
+            Projection::Equirectangular => {
+                let phi = sx * PI;
+                let theta = sy * PI / 2.0;
+                let dir =
+                    (forward * phi.cos() + right * phi.sin()) * theta.cos() + up * theta.sin();
+                Some((self.position, dir))
+            }
+        }
+    }
+
+    /// Maps normalised image coordinates to view-plane coordinates, with
+    /// `half` the half extent along `fov_axis` and square pixels.
+    fn scale(&self, sx: f32, sy: f32, half: f32, width: f32, height: f32) -> (f32, f32) {
+        match self.fov_axis {
+            FovAxis::Vertical => (sx * half * width / height, sy * half),
+            FovAxis::Horizontal => (sx * half, sy * half * height / width),
+        }
+    }
+
+    /// Ray through a perspective view-plane direction `dir` (unit length along
+    /// the view axis), from a point on the lens when the aperture is open.
+    fn thin_lens(&self, dir: Vec3, right: Vec3, up: Vec3, sample: Vec2) -> (Vec3, Vec3) {
+        if self.aperture <= 0.0 {
+            return (self.position, dir.normalize());
+        }
+        let focus = self.position + dir * self.focus_distance();
+        let lens = if self.blades >= 3 {
+            uniform_polygon(self.blades, self.blade_rotation, sample.x, sample.y)
+        } else {
+            uniform_disk(sample.x, sample.y)
+        } * self.aperture;
+        let origin = self.position + right * lens.x + up * lens.y;
+        (origin, (focus - origin).normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ray through `(px, py)` of a 200 x 100 image.
+    fn ray(camera: &Camera, px: f32, py: f32) -> Option<(Vec3, Vec3)> {
+        camera.generate_ray(px, py, 200, 100, Vec2::new(0.5, 0.5))
+    }
+
+    fn assert_direction(dir: Vec3, expected: Vec3) {
+        let expected = expected.normalize();
+        assert!(
+            (dir - expected).norm() < 1e-5,
+            "{:?} != {:?}",
+            dir,
+            expected
+        );
+    }
+
+    #[test]
+    fn perspective_centre_and_corner() {
+        let mut camera = Camera::default();
+        let (orig, dir) = ray(&camera, 100.0, 50.0).unwrap();
+        assert_eq!(orig, Vec3::new(0.0, 0.0, 0.0));
+        assert_direction(dir, Vec3::new(0.0, 0.0, -1.0));
+        // A 90 degree vertical field of view on a 2:1 image reaches twice as
+        // far horizontally.
+        assert_direction(
+            ray(&camera, 0.0, 0.0).unwrap().1,
+            Vec3::new(-2.0, 1.0, -1.0),
+        );
+        assert_direction(
+            ray(&camera, 200.0, 100.0).unwrap().1,
+            Vec3::new(2.0, -1.0, -1.0),
+        );
+        camera.fov_axis = FovAxis::Horizontal;
+        assert_direction(
+            ray(&camera, 0.0, 0.0).unwrap().1,
+            Vec3::new(-1.0, 0.5, -1.0),
+        );
+    }
+
+    #[test]
+    fn look_at_orients_the_view() {
+        let camera = Camera::look_at(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(5.0, 2.0, 3.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            PI / 2.0,
+        );
+        let (right, up, forward) = camera.basis();
+        assert_direction(forward, Vec3::new(1.0, 0.0, 0.0));
+        assert_direction(right, Vec3::new(0.0, 0.0, 1.0));
+        assert_direction(up, Vec3::new(0.0, 1.0, 0.0));
+        let (orig, dir) = ray(&camera, 100.0, 50.0).unwrap();
+        assert_eq!(orig, camera.position);
+        assert_direction(dir, Vec3::new(1.0, 0.0, 0.0));
+        assert_direction(
+            ray(&camera, 200.0, 50.0).unwrap().1,
+            Vec3::new(1.0, 0.0, 2.0),
+        );
+    }
+
+    #[test]
+    fn up_parallel_to_the_view_still_gives_a_basis() {
+        let camera = Camera::look_at(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, -3.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            PI / 2.0,
+        );
+        let (right, up, forward) = camera.basis();
+        assert_direction(forward, Vec3::new(0.0, -1.0, 0.0));
+        assert!((right.norm() - 1.0).abs() < 1e-5 && (up.norm() - 1.0).abs() < 1e-5);
+        assert!(right.dot(&forward).abs() < 1e-5 && up.dot(&forward).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = Camera {
+            projection: Projection::Orthographic { size: 4.0 },
+            ..Camera::default()
+        };
+        let (orig, dir) = ray(&camera, 100.0, 50.0).unwrap();
+        assert_eq!(orig, Vec3::new(0.0, 0.0, 0.0));
+        assert_direction(dir, Vec3::new(0.0, 0.0, -1.0));
+        let (orig, dir) = ray(&camera, 0.0, 0.0).unwrap();
+        assert!((orig - Vec3::new(-4.0, 2.0, 0.0)).norm() < 1e-5);
+        assert_direction(dir, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn fisheye_angle_grows_linearly_to_the_circle() {
+        let camera = Camera {
+            projection: Projection::Fisheye,
+            fov: PI,
+            ..Camera::default()
+        };
+        assert_direction(
+            ray(&camera, 100.0, 50.0).unwrap().1,
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        // The image circle spans the height, so its right edge is at x = 150.
+        assert_direction(
+            ray(&camera, 150.0, 50.0).unwrap().1,
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_direction(
+            ray(&camera, 125.0, 50.0).unwrap().1,
+            Vec3::new(1.0, 0.0, -1.0),
+        );
+        assert_direction(
+            ray(&camera, 100.0, 0.0).unwrap().1,
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert!(ray(&camera, 0.0, 0.0).is_none());
+        // A full 360 degree fisheye sees straight behind at its rim.
+        let camera = Camera {
+            fov: 2.0 * PI,
+            ..camera
+        };
+        assert_direction(
+            ray(&camera, 150.0, 50.0).unwrap().1,
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let camera = Camera {
+            projection: Projection::Equirectangular,
+            ..Camera::default()
+        };
+        assert_direction(
+            ray(&camera, 100.0, 50.0).unwrap().1,
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_direction(
+            ray(&camera, 150.0, 50.0).unwrap().1,
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_direction(ray(&camera, 0.0, 50.0).unwrap().1, Vec3::new(0.0, 0.0, 1.0));
+        assert_direction(
+            ray(&camera, 100.0, 0.0).unwrap().1,
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_direction(
+            ray(&camera, 100.0, 100.0).unwrap().1,
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn thin_lens_rays_meet_on_the_focus_plane() {
+        for &blades in &[0, 6] {
+            let camera = Camera {
+                aperture: 0.5,
+                focus_distance: Some(4.0),
+                blades,
+                ..Camera::default()
+            };
+            for &(px, py) in &[(100.0, 50.0), (30.0, 80.0)] {
+                let pinhole_camera = Camera {
+                    aperture: 0.0,
+                    ..camera
+                };
+                let (_, pinhole) = ray(&pinhole_camera, px, py).unwrap();
+                let focus = pinhole * (4.0 / -pinhole.z);
+                let mut spread = 0f32;
+                for &(u, v) in &[(0.1, 0.2), (0.9, 0.5), (0.4, 0.95)] {
+                    let (orig, dir) = camera
+                        .generate_ray(px, py, 200, 100, Vec2::new(u, v))
+                        .unwrap();
+                    assert!(orig.z == 0.0 && orig.norm() <= 0.5 + 1e-6);
+                    spread = spread.max(orig.norm());
+                    let t = (4.0 + orig.z) / -dir.z;
+                    assert!((orig + dir * t - focus).norm() < 1e-4);
+                }
+                assert!(spread > 0.1, "lens samples stayed at the centre");
+            }
+        }
+    }
+
+    #[test]
+    fn fov_limits_depend_on_the_projection() {
+        let camera = |projection, degrees: f32| Camera {
+            projection,
+            fov: degrees.to_radians(),
+            ..Camera::default()
+        };
+        assert!(camera(Projection::Perspective, 179.0).validate().is_ok());
+        assert!(camera(Projection::Perspective, 180.0).validate().is_err());
+        assert!(camera(Projection::Fisheye, 180.0).validate().is_ok());
+        assert!(camera(Projection::Fisheye, 360.0).validate().is_ok());
+        assert!(camera(Projection::Fisheye, 361.0).validate().is_err());
+        assert!(camera(Projection::Equirectangular, 360.0)
+            .validate()
+            .is_ok());
+        assert!(camera(Projection::Fisheye, 0.0).validate().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use ray::tonemap::{ToneMapper, ToneMapping, Transfer};
+use ray::{Aov, Filter, Integrator};
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "usage: ray [options]
+
+options:
+    --scene <path>      scene description (default: ./scenes/default.toml)
+    --width <pixels>    image width, overrides the scene
+    --height <pixels>   image height, overrides the scene
+    --fov <degrees>     camera field of view, overrides the scene; below
+                        180 for perspective, up to 360 for fisheye
+    --threads <n>       number of render threads (default: number of CPUs)
+    --max-depth <n>     maximum reflection/refraction depth
+    --integrator <name> whitted (default) or path
+    --spp <n>           samples per pixel (default: 1)
+    --filter <name>     pixel filter: box (default), tent, gaussian or mitchell
+    --light-samples <n> shadow rays per area light (default: 16)
+    --pbr               convert Phong materials to approximate PBR ones
+    --output <path>     output image path (default: ./images/render.png);
+                        {frame} and {timestamp} are substituted; the
+                        extension picks the format: png, exr, hdr or pfm
+    --half              store EXR channels as 16-bit half floats
+    --exposure <stops>  exposure adjustment for PNG output (default: 0)
+    --tonemap <name>    tone curve for PNG output: none (default), reinhard,
+                        reinhard-extended, aces, hable or agx
+    --white <value>     luminance reinhard-extended maps to white (default: 4)
+    --srgb              sRGB-encode PNG output (default)
+    --linear            write linear values to PNG instead of sRGB-encoding
+    --dither            dither PNG output before quantisation
+    --aov <name>        also write an AOV: depth, normal, albedo, object_id,
+                        material_id, direct or indirect; repeatable. EXR
+                        output stores them as layers, other formats as
+                        <name>.<aov>.<ext> next to the image; PNG output
+                        scales them into the displayable range
+    --frame <n>         frame number used for {frame} (default: 0)
+    --help              print this message";
+
+const DEFAULT_SCENE: &str = "./scenes/default.toml";
+const DEFAULT_OUTPUT: &str = "./images/render.png";
+
+pub struct Args {
+    pub scene: PathBuf,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub fov: Option<f32>,
+    pub threads: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub integrator: Option<Integrator>,
+    pub samples: Option<usize>,
+    pub filter: Option<Filter>,
+    pub light_samples: Option<usize>,
+    pub pbr: bool,
+    pub output: String,
+    pub half: bool,
+    pub tone_mapping: ToneMapping,
+    pub aovs: Vec<Aov>,
+    pub frame: usize,
+    pub help: bool,
+}
+
+fn value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn positive(flag: &str, value: usize) -> Result<usize, String> {
+    if value == 0 {
+        return Err(format!("{} must be greater than zero", flag));
+    }
+    Ok(value)
+}
+
+impl Args {
+    pub fn parse() -> Result<Args, String> {
+        Args::parse_from(env::args().skip(1))
+    }
+
+    pub fn parse_from<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+        let mut parsed = Args {
+            scene: PathBuf::from(DEFAULT_SCENE),
+            width: None,
+            height: None,
+            fov: None,
+            threads: None,
+            max_depth: None,
+            integrator: None,
+            samples: None,
+            filter: None,
+            light_samples: None,
+            pbr: false,
+            output: DEFAULT_OUTPUT.to_string(),
+            half: false,
+            tone_mapping: ToneMapping::default(),
+            aovs: vec![],
+            frame: 0,
+            help: false,
+        };
+        let mut white = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+            match flag {
+                "--scene" => parsed.scene = value::<String>(flag, args.next())?.into(),
+                "--width" => parsed.width = Some(positive(flag, value(flag, args.next())?)?),
+                "--height" => parsed.height = Some(positive(flag, value(flag, args.next())?)?),
+                "--fov" => {
+                    let fov: f32 = value(flag, args.next())?;
+                    if !(fov > 0.0 && fov <= 360.0) {
+                        return Err(format!("{} must be between 0 and 360 degrees", flag));
+                    }
+                    parsed.fov = Some(fov);
+                }
+                "--threads" => parsed.threads = Some(positive(flag, value(flag, args.next())?)?),
+                "--max-depth" => parsed.max_depth = Some(value(flag, args.next())?),
+                "--integrator" => {
+                    let name: String = value(flag, args.next())?;
+                    parsed.integrator = Some(match name.as_str() {
+                        "whitted" => Integrator::Whitted,
+                        "path" => Integrator::PathTracer,
+                        _ => return Err(format!("unknown integrator: {}", name)),
+                    });
+                }
+                "--spp" => parsed.samples = Some(positive(flag, value(flag, args.next())?)?),
+                "--light-samples" => {
+                    parsed.light_samples = Some(positive(flag, value(flag, args.next())?)?)
+                }
+                "--filter" => {
+                    let name: String = value(flag, args.next())?;
+                    match Filter::from_name(&name) {
+                        Some(filter) => parsed.filter = Some(filter),
+                        None => return Err(format!("unknown filter: {}", name)),
+                    }
+                }
+                "--output" | "-o" => parsed.output = value(flag, args.next())?,
+                "--half" => parsed.half = true,
+                "--pbr" => parsed.pbr = true,
+                "--exposure" => parsed.tone_mapping.exposure = value(flag, args.next())?,
+                "--tonemap" => {
+                    let name: String = value(flag, args.next())?;
+                    match ToneMapper::from_name(&name) {
+                        Some(mapper) => parsed.tone_mapping.mapper = mapper,
+                        None => return Err(format!("unknown tone mapper: {}", name)),
+                    }
+                }
+                "--white" => {
+                    let value: f32 = value(flag, args.next())?;
+                    if !(value > 0.0 && value.is_finite()) {
+                        return Err(format!("{} must be positive", flag));
+                    }
+                    white = Some(value);
+                }
+                "--srgb" => parsed.tone_mapping.transfer = Transfer::Srgb,
+                "--linear" => parsed.tone_mapping.transfer = Transfer::Linear,
+                "--dither" => parsed.tone_mapping.dither = true,
+                "--aov" => {
+                    let name: String = value(flag, args.next())?;
+                    match Aov::from_name(&name) {
+                        Some(aov) => parsed.aovs.push(aov),
+                        None => return Err(format!("unknown AOV: {}", name)),
+                    }
+                }
+                "--frame" => parsed.frame = value(flag, args.next())?,
+                "--help" | "-h" => parsed.help = true,
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+        // Applied last so that --white may come before --tonemap.
+        match (&mut parsed.tone_mapping.mapper, white) {
+            (ToneMapper::ReinhardExtended { white }, Some(value)) => *white = value,
+            (_, Some(_)) => {
+                return Err("--white only applies to --tonemap reinhard-extended".to_string())
+            }
+            _ => {}
+        }
+        Ok(parsed)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::image::Image;
+use crate::sampling::Distribution2D;
+use crate::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Distant lighting from an equirectangular (latitude-longitude) image. The
+/// image centre lies along -z with +y up, matching the equirectangular camera.
+pub struct Environment {
+    image: Image,
+    /// Rotation about the y axis in radians.
+    pub rotation: f32,
+    /// Multiplier applied to every texel.
+    pub intensity: f32,
+    /// Texel luminance weighted by the solid angle each row covers.
+    distribution: Distribution2D,
+}
+
+impl Environment {
+    pub fn new(image: Image, rotation: f32, intensity: f32) -> Environment {
+        let mut func = Vec::with_capacity(image.width * image.height);
+        for y in 0..image.height {
+            let sin_theta = (PI * (y as f32 + 0.5) / image.height as f32).sin();
+            for x in 0..image.width {
+                let c = image.get(x, y);
+                func.push((0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z) * sin_theta);
+            }
+        }
+        let distribution = Distribution2D::new(&func, image.width, image.height);
+        Environment {
+            image,
+            rotation,
+            intensity,
+            distribution,
+        }
+    }
+
+    /// Radiance arriving from direction `dir` (pointing away from the scene).
+    pub fn radiance(&self, dir: &Vec3) -> Vec3 {
+        let uv = self.direction_to_uv(dir);
+        self.lookup(&uv) * self.intensity
+    }
+
+    /// Direction sampled in proportion to the image's brightness, with its
+    /// radiance and solid-angle density.
+    pub fn sample(&self, u1: f32, u2: f32) -> (Vec3, Vec3, f32) {
+        let (uv, pdf) = self.distribution.sample(u1, u2);
+        let sin_theta = (uv.y * PI).sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0);
+        }
+        let dir = self.uv_to_direction(&uv);
+        let radiance = self.lookup(&uv) * self.intensity;
+        (dir, radiance, pdf / (2.0 * PI * PI * sin_theta))
+    }
+
+    /// Solid-angle density with which `sample` returns `dir`.
+    pub fn pdf(&self, dir: &Vec3) -> f32 {
+        let uv = self.direction_to_uv(dir);
+        let sin_theta = (uv.y * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(&uv) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn direction_to_uv(&self, dir: &Vec3) -> Vec2 {
+        let phi = dir.x.atan2(-dir.z) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = dir.y.clamp(-1.0, 1.0).acos() / PI;
+        Vec2::new(u, v)
+    }
+
+    fn uv_to_direction(&self, uv: &Vec2) -> Vec3 {
+        let phi = (uv.x - 0.5) * 2.0 * PI + self.rotation;
+        let theta = uv.y * PI;
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    /// Bilinear lookup, wrapping around horizontally.
+    fn lookup(&self, uv: &Vec2) -> Vec3 {
+        let width = self.image.width;
+        let height = self.image.height;
+        let x = uv.x * width as f32 - 0.5;
+        let y = (uv.y * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let x0 = (x0 as isize).rem_euclid(width as isize) as usize;
+        let x1 = (x0 + 1) % width;
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(height - 1);
+        let top = self.image.get(x0, y0) * (1.0 - fx) + self.image.get(x1, y0) * fx;
+        let bottom = self.image.get(x0, y1) * (1.0 - fx) + self.image.get(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
@@ -0,0 +1,304 @@
+use crate::image::Image;
+use std::io::{self, Write};
+
+/// Storage type of EXR channels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelType {
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn id(self) -> i32 {
+        match self {
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Float => 4,
+        }
+    }
+}
+
+/// Writes an uncompressed scanline OpenEXR file. Each `(name, image)` layer
+/// becomes the channels `name.R`, `name.G` and `name.B`; a layer with an empty
+/// name gives the plain `R`, `G`, `B` channels. All layers must have the same
+/// size.
+pub fn write_exr<W: Write>(
+    mut w: W,
+    layers: &[(&str, &Image)],
+    pixel_type: PixelType,
+) -> io::Result<()> {
+    let (width, height) = match layers.first() {
+        Some((_, image)) => (image.width, image.height),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no layers")),
+    };
+    if layers
+        .iter()
+        .any(|(_, image)| image.width != width || image.height != height)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "layers differ in size",
+        ));
+    }
+
+    // Channels are stored in alphabetical order of their names.
+    let mut channels: Vec<(String, &Image, usize)> = vec![];
+    for (name, image) in layers {
+        for (component, suffix) in ["R", "G", "B"].iter().enumerate() {
+            let channel = if name.is_empty() {
+                suffix.to_string()
+            } else {
+                format!("{}.{}", name, suffix)
+            };
+            channels.push((channel, image, component));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = vec![];
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    let long_names = channels.iter().any(|(name, _, _)| name.len() > 31);
+    let version: u32 = if long_names { 2 | 0x400 } else { 2 };
+    header.extend_from_slice(&version.to_le_bytes());
+
+    let mut chlist = vec![];
+    for (name, _, _) in &channels {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&pixel_type.id().to_le_bytes());
+        // pLinear and three reserved bytes, then x and y sampling.
+        chlist.extend_from_slice(&[0, 0, 0, 0]);
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+    }
+    chlist.push(0);
+    attribute(&mut header, "channels", "chlist", &chlist);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let mut window = vec![];
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    let mut center = vec![];
+    center.extend_from_slice(&0f32.to_le_bytes());
+    center.extend_from_slice(&0f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &center);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    w.write_all(&header)?;
+
+    // One chunk per scanline: y, byte count, then each channel's row.
+    let row_size = width * channels.len() * pixel_type.size();
+    let first_chunk = header.len() + height * 8;
+    for y in 0..height {
+        let offset = (first_chunk + y * (8 + row_size)) as u64;
+        w.write_all(&offset.to_le_bytes())?;
+    }
+    let mut row = Vec::with_capacity(row_size);
+    for y in 0..height {
+        w.write_all(&(y as i32).to_le_bytes())?;
+        w.write_all(&(row_size as i32).to_le_bytes())?;
+        row.clear();
+        for (_, image, component) in &channels {
+            for x in 0..width {
+                let value = image.get(x, y)[*component];
+                match pixel_type {
+                    PixelType::Half => row.extend_from_slice(&f32_to_half(value).to_le_bytes()),
+                    PixelType::Float => row.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        w.write_all(&row)?;
+    }
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// IEEE 754 half-precision bits of `value`, rounded to nearest even.
+pub fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        // Subnormal half, or zero if even the leading bit is shifted out.
+        if e < -10 {
+            return sign;
+        }
+        let m = mantissa | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let half = m >> shift;
+        let rest = m & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rest > halfway || (rest == halfway && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+    let half = ((e as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let round = rest > 0x1000 || (rest == 0x1000 && half & 1 == 1);
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity.
+    sign | (half + round as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+    use std::convert::TryInto;
+
+    /// Reference decoder for the half-precision format.
+    fn half_to_f32(h: u16) -> f32 {
+        let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = ((h >> 10) & 0x1f) as i32;
+        let mantissa = (h & 0x3ff) as f32;
+        sign * match exponent {
+            0 => mantissa * 2f32.powi(-24),
+            0x1f if mantissa == 0.0 => f32::INFINITY,
+            0x1f => f32::NAN,
+            _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+        }
+    }
+
+    #[test]
+    fn half_edge_cases() {
+        assert_eq!(f32_to_half(0.0), 0x0000);
+        assert_eq!(f32_to_half(-0.0), 0x8000);
+        assert_eq!(f32_to_half(1.0), 0x3c00);
+        assert_eq!(f32_to_half(-2.0), 0xc000);
+        // Largest finite half, and the first value that rounds past it.
+        assert_eq!(f32_to_half(65504.0), 0x7bff);
+        assert_eq!(f32_to_half(65519.0), 0x7bff);
+        assert_eq!(f32_to_half(65520.0), 0x7c00);
+        assert_eq!(f32_to_half(1e10), 0x7c00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_half(f32::NEG_INFINITY), 0xfc00);
+        let nan = f32_to_half(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x3ff, 0);
+        // Smallest normal, and subnormals down to the smallest one.
+        assert_eq!(f32_to_half(2f32.powi(-14)), 0x0400);
+        assert_eq!(f32_to_half(1023.0 * 2f32.powi(-24)), 0x03ff);
+        assert_eq!(f32_to_half(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_half(-2f32.powi(-24)), 0x8001);
+        // Halfway below the smallest subnormal rounds to even, i.e. zero;
+        // anything above it rounds up.
+        assert_eq!(f32_to_half(2f32.powi(-25)), 0x0000);
+        assert_eq!(f32_to_half(1.5 * 2f32.powi(-25)), 0x0001);
+        assert_eq!(f32_to_half(1e-10), 0x0000);
+        // Ties between normals round to even.
+        assert_eq!(f32_to_half(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_half(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn every_half_round_trips() {
+        for h in 0..=u16::MAX {
+            let value = half_to_f32(h);
+            if !value.is_nan() {
+                assert_eq!(f32_to_half(value), h, "{:#06x}", h);
+            }
+        }
+    }
+
+    fn read_i32(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn read_f32(bytes: &[u8], at: usize) -> f32 {
+        f32::from_bits(read_i32(bytes, at) as u32)
+    }
+
+    #[test]
+    fn two_layer_file_layout() {
+        let mut color = Image::new(2, 2);
+        let mut normal = Image::new(2, 2);
+        for y in 0..2 {
+            for x in 0..2 {
+                let i = (y * 2 + x) as f32;
+                color.set(x, y, Vec3::new(i, i + 10.0, i + 20.0));
+                normal.set(x, y, Vec3::new(-i, -i - 10.0, -i - 20.0));
+            }
+        }
+        let mut bytes = vec![];
+        write_exr(
+            &mut bytes,
+            &[("", &color), ("normal", &normal)],
+            PixelType::Float,
+        )
+        .unwrap();
+
+        assert_eq!(&bytes[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(read_i32(&bytes, 4), 2);
+        let chlist = b"channels\0chlist\0";
+        let at = bytes
+            .windows(chlist.len())
+            .position(|w| w == chlist)
+            .unwrap();
+        // Channel names sort with the unnamed layer's B, G, R first.
+        let names: Vec<&[u8]> = bytes[at + chlist.len() + 4..]
+            .chunks(18)
+            .take(3)
+            .map(|entry| &entry[..2])
+            .collect();
+        assert_eq!(names, [b"B\0", b"G\0", b"R\0"]);
+
+        // Six float channels of two pixels per row, after a header whose
+        // length follows from the file size.
+        let row_size = 6 * 2 * 4;
+        let header_len = bytes.len() - 2 * 8 - 2 * (8 + row_size);
+        assert_eq!(bytes[header_len - 1], 0);
+        let offset0 = u64::from_le_bytes(bytes[header_len..header_len + 8].try_into().unwrap());
+        let offset1 =
+            u64::from_le_bytes(bytes[header_len + 8..header_len + 16].try_into().unwrap());
+        assert_eq!(offset0 as usize, header_len + 16);
+        assert_eq!(offset1 as usize, header_len + 16 + 8 + row_size);
+
+        // Scanline 1: y, byte count, then channels B, G, R, normal.B,
+        // normal.G, normal.R.
+        let chunk = offset1 as usize;
+        assert_eq!(read_i32(&bytes, chunk), 1);
+        assert_eq!(read_i32(&bytes, chunk + 4), row_size as i32);
+        let data = chunk + 8;
+        let expected = [
+            22.0, 23.0, 12.0, 13.0, 2.0, 3.0, -22.0, -23.0, -12.0, -13.0, -2.0, -3.0,
+        ];
+        for (k, value) in expected.iter().enumerate() {
+            assert_eq!(read_f32(&bytes, data + 4 * k), *value);
+        }
+    }
+}
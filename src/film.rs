@@ -0,0 +1,130 @@
+use crate::filter::Filter;
+use crate::image::Image;
+use crate::Vec3;
+
+/// Weighted sample accumulator for the rectangle of pixels starting at
+/// `(x0, y0)`. Tiles splat into a private film covering the tile plus the
+/// filter footprint, which is then merged into the full-frame film.
+pub struct Film {
+    pub x0: usize,
+    pub y0: usize,
+    pub width: usize,
+    pub height: usize,
+    sums: Vec<Vec3>,
+    weights: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(x0: usize, y0: usize, width: usize, height: usize) -> Film {
+        Film {
+            x0,
+            y0,
+            width,
+            height,
+            sums: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    /// Adds a sample taken at continuous image position `(x, y)` to every pixel
+    /// of this film within the filter's reach.
+    pub fn add_sample(&mut self, x: f32, y: f32, value: Vec3, filter: &Filter) {
+        let radius = filter.radius();
+        let min_x = ((x - 0.5 - radius).ceil().max(self.x0 as f32)) as usize;
+        let min_y = ((y - 0.5 - radius).ceil().max(self.y0 as f32)) as usize;
+        let max_x = (x - 0.5 + radius).floor();
+        let max_y = (y - 0.5 + radius).floor();
+        if max_x < 0.0 || max_y < 0.0 {
+            return;
+        }
+        let max_x = (max_x as usize).min(self.x0 + self.width - 1);
+        let max_y = (max_y as usize).min(self.y0 + self.height - 1);
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let w = filter.evaluate(px as f32 + 0.5 - x, py as f32 + 0.5 - y);
+                if w == 0.0 {
+                    continue;
+                }
+                let index = (py - self.y0) * self.width + (px - self.x0);
+                self.sums[index] += value * w;
+                self.weights[index] += w;
+            }
+        }
+    }
+
+    /// Adds the contents of another film, which must lie within this one.
+    pub fn merge(&mut self, other: &Film) {
+        for y in 0..other.height {
+            for x in 0..other.width {
+                let src = y * other.width + x;
+                let dst = (other.y0 + y - self.y0) * self.width + (other.x0 + x - self.x0);
+                self.sums[dst] += other.sums[src];
+                self.weights[dst] += other.weights[src];
+            }
+        }
+    }
+
+    /// Normalised image. With `clamp_negative`, values pushed below zero by
+    /// negative filter lobes are clamped away; leave it off for signed data
+    /// such as normals.
+    pub fn to_image(&self, clamp_negative: bool) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for (index, pixel) in image.pixels.iter_mut().enumerate() {
+            let w = self.weights[index];
+            if w > 0.0 {
+                let c = self.sums[index] / w;
+                *pixel = if clamp_negative {
+                    c.map(|v| v.max(0.0))
+                } else {
+                    c
+                };
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_samples_reconstruct_exactly() {
+        let value = Vec3::new(0.25, 0.5, 2.0);
+        for name in &["box", "tent", "gaussian", "mitchell"] {
+            let filter = Filter::from_name(name).unwrap();
+            let mut film = Film::new(0, 0, 4, 3);
+            for j in 0..3 {
+                for i in 0..4 {
+                    for k in 0..4 {
+                        let x = i as f32 + 0.125 + 0.25 * k as f32;
+                        let y = j as f32 + 0.875 - 0.25 * k as f32;
+                        film.add_sample(x, y, value, &filter);
+                    }
+                }
+            }
+            let image = film.to_image(false);
+            for p in &image.pixels {
+                assert!((p - value).norm() < 1e-5, "{}: {:?}", name, p);
+            }
+        }
+    }
+
+    #[test]
+    fn merge_matches_splatting_into_one_film() {
+        let filter = Filter::from_name("tent").unwrap();
+        let mut whole = Film::new(0, 0, 6, 4);
+        let mut merged = Film::new(0, 0, 6, 4);
+        let mut part = Film::new(1, 1, 4, 3);
+        for (k, &(x, y)) in [(2.3, 2.1), (3.9, 1.5), (1.5, 3.5)].iter().enumerate() {
+            let value = Vec3::new(k as f32, 1.0, 0.5);
+            whole.add_sample(x, y, value, &filter);
+            part.add_sample(x, y, value, &filter);
+        }
+        merged.merge(&part);
+        let (a, b) = (whole.to_image(false), merged.to_image(false));
+        for (p, q) in a.pixels.iter().zip(&b.pixels) {
+            assert!((p - q).norm() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+/// Pixel reconstruction filter. Each sample contributes to every pixel whose
+/// centre lies within `radius` of it, weighted by `evaluate`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Box {
+        radius: f32,
+    },
+    Tent {
+        radius: f32,
+    },
+    Gaussian {
+        radius: f32,
+        alpha: f32,
+    },
+    /// Mitchell–Netravali cubic; `b = c = 1/3` is the recommended setting.
+    Mitchell {
+        radius: f32,
+        b: f32,
+        c: f32,
+    },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    /// Filter of the given kind with its usual default parameters.
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "box" => Some(Filter::Box { radius: 0.5 }),
+            "tent" => Some(Filter::Tent { radius: 1.0 }),
+            "gaussian" => Some(Filter::Gaussian {
+                radius: 1.5,
+                alpha: 2.0,
+            }),
+            "mitchell" => Some(Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. } => radius,
+        }
+    }
+
+    /// Weight for a pixel whose centre is `(dx, dy)` from the sample.
+    /// Separable, so this is the product of the 1D filter along each axis.
+    pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let d = x.abs();
+        match *self {
+            // Half-open, so a sample on the edge between two pixels only
+            // counts towards the one it lies in.
+            Filter::Box { radius } => {
+                if -radius < x && x <= radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Tent { radius } => (radius - d).max(0.0),
+            Filter::Gaussian { radius, alpha } => {
+                let g = |d: f32| (-alpha * d * d).exp();
+                (g(d) - g(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                let x = 2.0 * d / radius;
+                if x >= 2.0 {
+                    0.0
+                } else if x >= 1.0 {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [&str; 4] = ["box", "tent", "gaussian", "mitchell"];
+
+    #[test]
+    fn weights_vanish_outside_the_radius() {
+        for name in &FILTERS {
+            let filter = Filter::from_name(name).unwrap();
+            let r = filter.radius();
+            for &d in &[r + 0.01, r + 1.0] {
+                assert_eq!(filter.evaluate(0.0, d), 0.0, "{} at {}", name, d);
+                assert_eq!(filter.evaluate(-d, 0.0), 0.0, "{} at {}", name, -d);
+            }
+            assert!(filter.evaluate(-r, 0.0).abs() < 1e-6, "{} at {}", name, -r);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+        }
+    }
+
+    #[test]
+    fn box_support_is_half_open() {
+        let filter = Filter::default();
+        // A sample on the left edge of a pixel belongs to it, not to the
+        // pixel on the left.
+        assert_eq!(filter.evaluate(0.5, 0.0), 1.0);
+        assert_eq!(filter.evaluate(-0.5, 0.0), 0.0);
+        assert_eq!(filter.evaluate(0.5, 0.5), 1.0);
+        assert_eq!(filter.evaluate(0.5, -0.5), 0.0);
+    }
+
+    /// Sum of the 1D weights a sample at `x` gives to all pixel centres.
+    fn total_weight(filter: &Filter, x: f32) -> f32 {
+        (-4..=4)
+            .map(|i| filter.evaluate(i as f32 + 0.5 - x, 0.0) / filter.evaluate(0.0, 0.0))
+            .sum()
+    }
+
+    #[test]
+    fn box_and_tent_weights_sum_to_one() {
+        for name in &["box", "tent"] {
+            let filter = Filter::from_name(name).unwrap();
+            for k in 0..=16 {
+                let x = k as f32 / 16.0;
+                let total = total_weight(&filter, x);
+                assert!((total - 1.0).abs() < 1e-5, "{} at {}: {}", name, x, total);
+            }
+        }
+    }
+
+    #[test]
+    fn every_sample_carries_weight() {
+        for name in &FILTERS {
+            let filter = Filter::from_name(name).unwrap();
+            for k in 0..=16 {
+                let x = k as f32 / 16.0;
+                assert!(total_weight(&filter, x) > 0.0, "{} at {}", name, x);
+            }
+        }
+    }
+}
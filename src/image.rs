@@ -0,0 +1,347 @@
+use crate::exr::write_exr;
+use crate::output::{aov_path, write_atomic, Format, OutputError};
+use crate::Vec3;
+use png::HasParameters;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// Linear float framebuffer, stored row by row from the top-left corner.
+#[derive(Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Vec3>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Vec3::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Vec3) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::with_capacity(self.pixels.len() * 4);
+        for p in &self.pixels {
+            data.push((1f32.min(p.x) * 255.0) as u8);
+            data.push((1f32.min(p.y) * 255.0) as u8);
+            data.push((1f32.min(p.z) * 255.0) as u8);
+            data.push(255u8);
+        }
+        data
+    }
+
+    pub fn write_png<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgba8())?;
+        Ok(())
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), OutputError> {
+        write_atomic(path, |w| self.write_png(w))
+    }
+
+    /// Radiance RGBE with flat (uncompressed) scanlines.
+    pub fn write_hdr<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write!(
+            w,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.height, self.width
+        )?;
+        let mut row = Vec::with_capacity(self.width * 4);
+        for y in 0..self.height {
+            row.clear();
+            for x in 0..self.width {
+                row.extend_from_slice(&to_rgbe(&self.get(x, y)));
+            }
+            w.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    /// Portable float map: little-endian RGB floats, bottom row first.
+    pub fn write_pfm<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write!(w, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let mut row = Vec::with_capacity(self.width * 12);
+        for y in (0..self.height).rev() {
+            row.clear();
+            for x in 0..self.width {
+                let p = self.get(x, y);
+                for c in &[p.x, p.y, p.z] {
+                    row.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+            w.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the image with extra named buffers: as layers of the same file
+    /// for EXR, otherwise as separate files named by `aov_path`.
+    pub fn save_with_aovs(
+        &self,
+        path: &Path,
+        format: Format,
+        aovs: &[(&str, &Image)],
+    ) -> Result<(), OutputError> {
+        if let Format::Exr(pixel_type) = format {
+            let mut layers = vec![("", self)];
+            layers.extend_from_slice(aovs);
+            return write_atomic(path, |w| write_exr(w, &layers, pixel_type));
+        }
+        self.save(path, format)?;
+        for (name, image) in aovs {
+            image.save(&aov_path(path, name), format)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the image in `format`; only PNG loses values above 1.
+    pub fn save(&self, path: &Path, format: Format) -> Result<(), OutputError> {
+        write_atomic(path, |w| match format {
+            Format::Png => self.write_png(w),
+            Format::Exr(pixel_type) => write_exr(w, &[("", self)], pixel_type),
+            Format::Hdr => self.write_hdr(w),
+            Format::Pfm => self.write_pfm(w),
+        })
+    }
+}
+
+impl Image {
+    /// Loads a PNG or Radiance `.hdr` file, chosen by extension. PNG values are
+    /// scaled to `[0, 1]` as stored, without decoding any transfer function.
+    pub fn load(path: &Path) -> io::Result<Image> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let file = BufReader::new(File::open(path)?);
+        match extension.as_str() {
+            "png" => Image::read_png(file),
+            "hdr" => Image::read_hdr(file),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unsupported image format; use .png or .hdr",
+            )),
+        }
+    }
+
+    /// Decodes values stored with the sRGB transfer function to linear.
+    pub fn srgb_to_linear(&self) -> Image {
+        let decode = |v: f32| {
+            if v <= 0.04045 {
+                v / 12.92
+            } else {
+                ((v + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        let mut image = self.clone();
+        for p in &mut image.pixels {
+            *p = p.map(decode);
+        }
+        image
+    }
+
+    pub fn read_png<R: Read>(r: R) -> io::Result<Image> {
+        // The decoder expands palettes and strips 16-bit samples by default.
+        let (info, mut reader) = png::Decoder::new(r).read_info()?;
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+        let channels = info.color_type.samples();
+        let width = info.width as usize;
+        let height = info.height as usize;
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            let row = &data[y * info.line_size..];
+            for x in 0..width {
+                let p = &row[x * channels..];
+                let c = |i: usize| p[i] as f32 / 255.0;
+                // Alpha, if any, is ignored.
+                let color = if channels < 3 {
+                    Vec3::new(c(0), c(0), c(0))
+                } else {
+                    Vec3::new(c(0), c(1), c(2))
+                };
+                image.set(x, y, color);
+            }
+        }
+        Ok(image)
+    }
+
+    /// Reads Radiance RGBE with flat or run-length encoded scanlines, in the
+    /// standard `-Y height +X width` orientation.
+    pub fn read_hdr<R: BufRead>(mut r: R) -> io::Result<Image> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        if !line.starts_with("#?") {
+            return Err(invalid("not a Radiance file"));
+        }
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Err(invalid("missing resolution line"));
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid("only RGBE pixels are supported"));
+            }
+        }
+        line.clear();
+        r.read_line(&mut line)?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (height, width) = match fields.as_slice() {
+            ["-Y", h, "+X", w] => match (h.parse(), w.parse()) {
+                (Ok(h), Ok(w)) => (h, w),
+                _ => return Err(invalid("bad resolution line")),
+            },
+            _ => return Err(invalid("unsupported image orientation")),
+        };
+
+        let mut image = Image::new(width, height);
+        let mut row = vec![0u8; width * 4];
+        for y in 0..height {
+            read_rgbe_scanline(&mut r, &mut row)?;
+            for x in 0..width {
+                let p = &row[x * 4..x * 4 + 4];
+                image.set(x, y, from_rgbe([p[0], p[1], p[2], p[3]]));
+            }
+        }
+        Ok(image)
+    }
+}
+
+/// Reads one scanline of `row.len() / 4` pixels into `row` as RGBE quadruples.
+fn read_rgbe_scanline<R: Read>(r: &mut R, row: &mut [u8]) -> io::Result<()> {
+    let width = row.len() / 4;
+    let mut head = [0u8; 4];
+    r.read_exact(&mut head)?;
+    let rle = (8..0x8000).contains(&width)
+        && head[0] == 2
+        && head[1] == 2
+        && ((head[2] as usize) << 8 | head[3] as usize) == width;
+    if !rle {
+        row[..4].copy_from_slice(&head);
+        return r.read_exact(&mut row[4..]);
+    }
+    // Each component is stored separately as runs and literal spans.
+    let mut byte = [0u8; 1];
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            r.read_exact(&mut byte)?;
+            let (count, run) = if byte[0] > 128 {
+                (byte[0] as usize - 128, true)
+            } else {
+                (byte[0] as usize, false)
+            };
+            if count == 0 || x + count > width {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "bad run-length encoding",
+                ));
+            }
+            if run {
+                r.read_exact(&mut byte)?;
+            }
+            for _ in 0..count {
+                if !run {
+                    r.read_exact(&mut byte)?;
+                }
+                row[x * 4 + component] = byte[0];
+                x += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn from_rgbe(p: [u8; 4]) -> Vec3 {
+    if p[3] == 0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2f32.powi(p[3] as i32 - 136);
+    Vec3::new(
+        (p[0] as f32 + 0.5) * scale,
+        (p[1] as f32 + 0.5) * scale,
+        (p[2] as f32 + 0.5) * scale,
+    )
+}
+
+/// Shared-exponent encoding of a linear colour; negative values become 0.
+fn to_rgbe(color: &Vec3) -> [u8; 4] {
+    let v = color.x.max(color.y).max(color.z);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // v = m * 2^e with m in [0.5, 1); v is normal here, so read e off the bits.
+    let e = ((v.to_bits() >> 23) & 0xff) as i32 - 126;
+    let scale = 256.0 / 2f32.powi(e);
+    let byte = |c: f32| (c.max(0.0) * scale).min(255.0) as u8;
+    [byte(color.x), byte(color.y), byte(color.z), (e + 128) as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgbe_round_trip() {
+        let colors = [
+            Vec3::new(1.0, 0.5, 0.25),
+            Vec3::new(1000.0, 1.0, 0.0),
+            Vec3::new(0.001, 0.002, 0.003),
+            Vec3::new(3.0e5, 2.0e5, 1.0e5),
+        ];
+        for color in &colors {
+            let decoded = from_rgbe(to_rgbe(color));
+            let max = color.x.max(color.y).max(color.z);
+            for k in 0..3 {
+                // One step of the shared 8-bit mantissa.
+                assert!((decoded[k] - color[k]).abs() <= max / 128.0, "{:?}", color);
+            }
+        }
+        assert_eq!(to_rgbe(&Vec3::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(from_rgbe([0, 0, 0, 0]), Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(to_rgbe(&Vec3::new(1.0, -1.0, 0.0))[1], 0);
+    }
+
+    #[test]
+    fn pfm_header_and_bottom_row_first() {
+        let mut image = Image::new(2, 3);
+        for y in 0..3 {
+            for x in 0..2 {
+                image.set(x, y, Vec3::new(x as f32, y as f32, -1.0));
+            }
+        }
+        let mut bytes = vec![];
+        image.write_pfm(&mut bytes).unwrap();
+        let header = b"PF\n2 3\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(floats.len(), 2 * 3 * 3);
+        // Rows run from the bottom of the image (y = 2) to the top.
+        assert_eq!(&floats[0..6], &[0.0, 2.0, -1.0, 1.0, 2.0, -1.0]);
+        assert_eq!(&floats[12..18], &[0.0, 0.0, -1.0, 1.0, 0.0, -1.0]);
+    }
+}
@@ -0,0 +1,42 @@
+extern crate nalgebra as na;
+
+pub mod aov;
+pub mod bvh;
+pub mod camera;
+pub mod environment;
+pub mod exr;
+pub mod film;
+pub mod filter;
+pub mod image;
+pub mod light;
+pub mod loader;
+pub mod mesh;
+pub mod noise;
+pub mod obj;
+pub mod output;
+pub mod path;
+pub mod pbr;
+pub mod render;
+pub mod sampling;
+pub mod scene;
+pub mod scheduler;
+pub mod shape;
+pub mod texture;
+pub mod tonemap;
+
+pub use aov::Aov;
+pub use camera::{Camera, FovAxis, Projection};
+pub use environment::Environment;
+pub use filter::Filter;
+pub use image::Image;
+pub use light::{Falloff, LightShape, Spot};
+pub use mesh::{Mesh, Triangle};
+pub use render::{render, render_aovs, Differentials, Integrator, RenderSettings, Tracer};
+pub use scene::{Light, Lights, Material, Scene};
+pub use shape::{Hit, Plane, Shape, Sphere};
+pub use texture::{ImageTexture, Pattern, Procedural, Texture, TextureFilter, TextureSpace, Wrap};
+pub use tonemap::ToneMapping;
+
+pub type Vec2 = na::Vector2<f32>;
+pub type Vec3 = na::Vector3<f32>;
+pub type Vec4 = na::Vector4<f32>;
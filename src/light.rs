@@ -0,0 +1,304 @@
+use crate::sampling::{orthonormal_basis, uniform_disk, Rng};
+use crate::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Emitting surface of a light. Area lights are not part of the scene
+/// geometry; use an emissive material for a light that should be visible.
+#[derive(Clone, Copy, Debug)]
+pub enum LightShape {
+    Point,
+    /// Ball of `radius` around the light position, emitting in all directions.
+    Sphere {
+        radius: f32,
+    },
+    /// Rectangle around the light position spanning `±u` and `±v`, emitting
+    /// towards `normal`.
+    Rect {
+        normal: Vec3,
+        u: Vec3,
+        v: Vec3,
+    },
+    /// Disk of `radius` around the light position, emitting towards `normal`.
+    Disk {
+        normal: Vec3,
+        radius: f32,
+    },
+    /// Infinitely distant light shining along `direction`, like the sun. The
+    /// position is ignored and there is no falloff.
+    Directional {
+        direction: Vec3,
+    },
+}
+
+/// How a light dims with the distance `d` to the shading point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    /// Constant intensity at any distance.
+    None,
+    /// `1 / d^2`, as for a physical light.
+    InverseSquare,
+    /// `1 / (constant + linear * d + quadratic * d^2)`.
+    Custom {
+        constant: f32,
+        linear: f32,
+        quadratic: f32,
+    },
+}
+
+impl Falloff {
+    pub fn attenuation(&self, d: f32) -> f32 {
+        match *self {
+            Falloff::None => 1.0,
+            Falloff::InverseSquare => 1.0 / (d * d),
+            Falloff::Custom {
+                constant,
+                linear,
+                quadratic,
+            } => 1.0 / (constant + linear * d + quadratic * d * d),
+        }
+    }
+}
+
+/// Cone of a spotlight: full intensity within `cos_inner` of `direction`,
+/// fading smoothly to nothing at `cos_outer`.
+#[derive(Clone, Copy, Debug)]
+pub struct Spot {
+    pub direction: Vec3,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+}
+
+impl Spot {
+    /// Builds a cone from half-angles in radians.
+    pub fn new(direction: Vec3, inner: f32, outer: f32) -> Spot {
+        Spot {
+            direction: direction.normalize(),
+            cos_inner: inner.min(outer).cos(),
+            cos_outer: outer.cos(),
+        }
+    }
+
+    /// Fraction of the intensity emitted along the unit vector `dir`.
+    pub fn factor(&self, dir: &Vec3) -> f32 {
+        let cos = dir.dot(&self.direction);
+        if cos >= self.cos_inner {
+            return 1.0;
+        }
+        let t = ((cos - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Light {
+    /// Centre of the light.
+    pub position: Vec3,
+    pub intensity: f32,
+    pub color: Vec3,
+    pub shape: LightShape,
+    pub falloff: Falloff,
+    /// Restricts emission to a cone, making this a spotlight.
+    pub spot: Option<Spot>,
+    /// Shadow rays per shading point for area lights; `RenderSettings::
+    /// light_samples` when `None`.
+    pub samples: Option<usize>,
+}
+
+/// One point sampled on a light, as seen from a shading point.
+pub struct LightSample {
+    /// Unit direction from the shading point to the light.
+    pub dir: Vec3,
+    pub distance: f32,
+    /// Coloured intensity arriving along `dir`, attenuated and already divided
+    /// by the sample count.
+    pub intensity: Vec3,
+}
+
+impl Light {
+    pub fn point(position: Vec3, intensity: f32) -> Light {
+        Light {
+            position,
+            intensity,
+            color: Vec3::new(1.0, 1.0, 1.0),
+            shape: LightShape::Point,
+            falloff: Falloff::None,
+            spot: None,
+            samples: None,
+        }
+    }
+
+    /// Calls `visit` with stratified samples of the light as seen from `point`.
+    /// An area light spreads its intensity over its surface, so it is as
+    /// bright as a point light at its centre but casts soft shadows.
+    pub fn sample<F>(&self, point: &Vec3, default_samples: usize, rng: &mut Rng, mut visit: F)
+    where
+        F: FnMut(LightSample),
+    {
+        let intensity = self.color * self.intensity;
+        match self.shape {
+            LightShape::Directional { direction } => {
+                visit(LightSample {
+                    dir: -direction.normalize(),
+                    distance: f32::MAX,
+                    intensity,
+                });
+                return;
+            }
+            LightShape::Point => {
+                let offset = self.position - point;
+                let dir = offset.normalize();
+                let distance = offset.norm();
+                let scale = self.falloff.attenuation(distance) * self.spot_factor(&dir);
+                if scale > 0.0 {
+                    visit(LightSample {
+                        dir,
+                        distance,
+                        intensity: intensity * scale,
+                    });
+                }
+                return;
+            }
+            _ => {}
+        }
+        let strata = (self.samples.unwrap_or(default_samples).max(1) as f32)
+            .sqrt()
+            .ceil() as usize;
+        let count = (strata * strata) as f32;
+        for s in 0..strata * strata {
+            let u = Vec2::new(
+                ((s % strata) as f32 + rng.next_f32()) / strata as f32,
+                ((s / strata) as f32 + rng.next_f32()) / strata as f32,
+            );
+            let (position, cosine) = self.sample_surface(point, &u);
+            let offset = position - point;
+            let distance = offset.norm();
+            if distance <= 0.0 || cosine <= 0.0 {
+                continue;
+            }
+            let dir = offset / distance;
+            let scale =
+                cosine * self.falloff.attenuation(distance) * self.spot_factor(&dir) / count;
+            if scale > 0.0 {
+                visit(LightSample {
+                    dir,
+                    distance,
+                    intensity: intensity * scale,
+                });
+            }
+        }
+    }
+
+    /// Cone attenuation for light leaving towards `-dir`.
+    fn spot_factor(&self, dir: &Vec3) -> f32 {
+        match &self.spot {
+            Some(spot) => spot.factor(&-dir),
+            None => 1.0,
+        }
+    }
+
+    /// Point on the light for `u` in `[0, 1)^2` and the emission cosine towards
+    /// `point` (1 for shapes that emit uniformly).
+    fn sample_surface(&self, point: &Vec3, u: &Vec2) -> (Vec3, f32) {
+        match self.shape {
+            LightShape::Point | LightShape::Directional { .. } => (self.position, 1.0),
+            LightShape::Sphere { radius } => {
+                // Uniform over the hemisphere facing the shading point.
+                let towards = (point - self.position).normalize();
+                let z = u.x;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                let phi = 2.0 * PI * u.y;
+                let (t, b) = orthonormal_basis(&towards);
+                let n = t * (r * phi.cos()) + b * (r * phi.sin()) + towards * z;
+                (self.position + n * radius, 1.0)
+            }
+            LightShape::Rect {
+                normal,
+                u: eu,
+                v: ev,
+            } => {
+                let position = self.position + eu * (2.0 * u.x - 1.0) + ev * (2.0 * u.y - 1.0);
+                (position, emission_cosine(&normal, &position, point))
+            }
+            LightShape::Disk { normal, radius } => {
+                let d = uniform_disk(u.x, u.y) * radius;
+                let (t, b) = orthonormal_basis(&normal);
+                let position = self.position + t * d.x + b * d.y;
+                (position, emission_cosine(&normal, &position, point))
+            }
+        }
+    }
+}
+
+fn emission_cosine(normal: &Vec3, position: &Vec3, point: &Vec3) -> f32 {
+    (point - position).normalize().dot(normal).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falloff_attenuation() {
+        assert_eq!(Falloff::None.attenuation(3.0), 1.0);
+        assert_eq!(Falloff::InverseSquare.attenuation(2.0), 0.25);
+        let custom = Falloff::Custom {
+            constant: 1.0,
+            linear: 0.5,
+            quadratic: 0.25,
+        };
+        assert_eq!(custom.attenuation(0.0), 1.0);
+        assert_eq!(custom.attenuation(2.0), 1.0 / 3.0);
+    }
+
+    /// Unit vector at `degrees` from straight down, tilted towards +x.
+    fn from_down(degrees: f32) -> Vec3 {
+        let a = degrees.to_radians();
+        Vec3::new(a.sin(), -a.cos(), 0.0)
+    }
+
+    #[test]
+    fn spot_fades_between_the_cone_edges() {
+        let spot = Spot::new(
+            Vec3::new(0.0, -2.0, 0.0),
+            20f32.to_radians(),
+            30f32.to_radians(),
+        );
+        assert_eq!(spot.factor(&from_down(0.0)), 1.0);
+        assert_eq!(spot.factor(&from_down(19.9)), 1.0);
+        assert!((spot.factor(&from_down(20.0)) - 1.0).abs() < 1e-3);
+        let middle = spot.factor(&from_down(25.0));
+        assert!(middle > 0.3 && middle < 0.7, "{}", middle);
+        assert!(spot.factor(&from_down(22.0)) > middle);
+        assert!(spot.factor(&from_down(30.0)) < 1e-3);
+        assert_eq!(spot.factor(&from_down(30.1)), 0.0);
+        assert_eq!(spot.factor(&from_down(180.0)), 0.0);
+    }
+
+    #[test]
+    fn spot_with_equal_angles_has_a_hard_edge() {
+        let spot = Spot::new(Vec3::new(0.0, -1.0, 0.0), 0.5, 0.5);
+        let edge = 0.5f32.to_degrees();
+        assert_eq!(spot.factor(&from_down(edge - 0.1)), 1.0);
+        assert_eq!(spot.factor(&from_down(edge + 0.1)), 0.0);
+    }
+
+    #[test]
+    fn point_spotlight_only_lights_inside_its_cone() {
+        let light = Light {
+            spot: Some(Spot::new(
+                Vec3::new(0.0, -1.0, 0.0),
+                10f32.to_radians(),
+                20f32.to_radians(),
+            )),
+            ..Light::point(Vec3::new(0.0, 0.0, 0.0), 2.0)
+        };
+        let mut rng = Rng::new(0, 0);
+        let mut lit = |point: Vec3| {
+            let mut total = 0.0;
+            light.sample(&point, 1, &mut rng, |sample| total += sample.intensity.x);
+            total
+        };
+        assert_eq!(lit(from_down(5.0) * 3.0), 2.0);
+        assert_eq!(lit(from_down(25.0) * 3.0), 0.0);
+    }
+}
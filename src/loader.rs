@@ -0,0 +1,1098 @@
+use crate::camera::{FovAxis, Projection};
+use crate::environment::Environment;
+use crate::image::Image;
+use crate::light::{Falloff, LightShape, Spot};
+use crate::obj::{load_obj, ObjError};
+use crate::pbr::Pbr;
+use crate::render::RenderSettings;
+use crate::scene::{Light, Material, Scene};
+use crate::shape::{Plane, Sphere};
+use crate::texture::{
+    ImageTexture, Pattern, Procedural, Texture, TextureFilter, TextureSpace, Wrap,
+};
+use crate::{Vec2, Vec3, Vec4};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Obj(ObjError),
+    Image(PathBuf, io::Error),
+    UnknownMaterial {
+        object: String,
+        name: String,
+    },
+    UnknownTexture {
+        material: String,
+        name: String,
+    },
+    InvalidLight {
+        index: usize,
+        message: &'static str,
+    },
+    InvalidMaterial {
+        name: String,
+        message: &'static str,
+    },
+    InvalidShape {
+        object: String,
+        message: &'static str,
+    },
+    InvalidSize {
+        width: usize,
+        height: usize,
+    },
+    InvalidCamera {
+        message: &'static str,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "cannot read scene: {}", e),
+            SceneError::Parse(e) => write!(f, "invalid scene: {}", e),
+            SceneError::Obj(e) => write!(f, "invalid mesh: {}", e),
+            SceneError::Image(path, e) => write!(f, "cannot load {}: {}", path.display(), e),
+            SceneError::UnknownMaterial { object, name } => {
+                write!(f, "{} uses unknown material \"{}\"", object, name)
+            }
+            SceneError::UnknownTexture { material, name } => write!(
+                f,
+                "material \"{}\" uses unknown texture \"{}\"",
+                material, name
+            ),
+            SceneError::InvalidMaterial { name, message } => {
+                write!(f, "material \"{}\": {}", name, message)
+            }
+            SceneError::InvalidLight { index, message } => {
+                write!(f, "light #{}: {}", index, message)
+            }
+            SceneError::InvalidShape { object, message } => write!(f, "{}: {}", object, message),
+            SceneError::InvalidSize { width, height } => {
+                write!(f, "invalid image size {}x{}", width, height)
+            }
+            SceneError::InvalidCamera { message } => write!(f, "camera: {}", message),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(e: ObjError) -> Self {
+        SceneError::Obj(e)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(e: toml::de::Error) -> Self {
+        SceneError::Parse(e)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    width: Option<usize>,
+    height: Option<usize>,
+    background: Option<[f32; 3]>,
+    environment: Option<EnvironmentFile>,
+    #[serde(default)]
+    camera: CameraFile,
+    /// Sorted by name, so texture and material ids are the same every run.
+    #[serde(default)]
+    textures: BTreeMap<String, TextureFile>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialFile>,
+    #[serde(default)]
+    spheres: Vec<SphereFile>,
+    #[serde(default)]
+    meshes: Vec<MeshFile>,
+    #[serde(default)]
+    planes: Vec<PlaneFile>,
+    #[serde(default)]
+    lights: Vec<LightFile>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraFile {
+    position: Option<[f32; 3]>,
+    target: Option<[f32; 3]>,
+    up: Option<[f32; 3]>,
+    /// Field of view in degrees.
+    fov: Option<f32>,
+    /// Axis the field of view spans: "vertical" (default) or "horizontal".
+    fov_axis: Option<FovAxisFile>,
+    /// "perspective" (default), "orthographic", "fisheye" or "equirectangular".
+    projection: Option<ProjectionFile>,
+    /// View extent in world units for orthographic cameras.
+    size: Option<f32>,
+    /// Lens radius; zero or absent for a pinhole.
+    aperture: Option<f32>,
+    /// Defaults to the distance to the target.
+    focus_distance: Option<f32>,
+    /// Aperture blade count for polygonal bokeh.
+    blades: Option<u32>,
+    /// Blade rotation in degrees.
+    blade_rotation: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ProjectionFile {
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Equirectangular,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FovAxisFile {
+    Vertical,
+    Horizontal,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentFile {
+    /// Equirectangular PNG or Radiance .hdr image, relative to the scene file.
+    path: PathBuf,
+    /// Rotation about the y axis in degrees.
+    rotation: Option<f32>,
+    intensity: Option<f32>,
+    /// Decode values from sRGB to linear; defaults to true for PNG files, as
+    /// for image textures.
+    srgb: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialFile {
+    /// Also the index of refraction of PBR materials, 1.5 by default.
+    refractive_index: Option<f32>,
+    #[serde(default)]
+    diffuse_color: [f32; 3],
+    /// Replaces the diffuse or base colour.
+    diffuse_texture: Option<String>,
+    /// Replace the PBR roughness, metalness and transmission with a
+    /// texture's channel mean.
+    roughness_texture: Option<String>,
+    metallic_texture: Option<String>,
+    transmission_texture: Option<String>,
+    /// Replaces the emission.
+    emission_texture: Option<String>,
+    /// Tangent-space normal map, always loaded without sRGB decoding.
+    normal_texture: Option<String>,
+    /// Height map, its values multiplied by `bump_scale` (default 1) to give
+    /// world units.
+    bump_texture: Option<String>,
+    bump_scale: Option<f32>,
+    /// Phong lobe weights, required unless the material is PBR.
+    albedo: Option<[f32; 4]>,
+    specular_exponent: Option<f32>,
+    /// Setting any of the following makes the material PBR.
+    base_color: Option<[f32; 3]>,
+    metallic: Option<f32>,
+    roughness: Option<f32>,
+    /// Normal-incidence reflectance of the dielectric part, as an alternative
+    /// to `refractive_index`.
+    specular: Option<f32>,
+    transmission: Option<f32>,
+    /// Emitted radiance; emissive shapes light the scene like area lights.
+    #[serde(default)]
+    emission: [f32; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureFile {
+    Checker {
+        even: [f32; 3],
+        odd: [f32; 3],
+        /// Checks per unit length.
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+    },
+    /// The procedural textures below blend `colors` (default black to white)
+    /// by a pattern with features about `1 / scale` across, evaluated in
+    /// `space`: "world" (default) or "object".
+    Stripes {
+        colors: Option<[[f32; 3]; 2]>,
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+        /// Direction across the stripes; defaults to x.
+        axis: Option<[f32; 3]>,
+    },
+    Noise {
+        colors: Option<[[f32; 3]; 2]>,
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+    },
+    Fbm {
+        colors: Option<[[f32; 3]; 2]>,
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+        /// Number of noise layers (default 5), each `lacunarity` (default 2)
+        /// times finer and `gain` (default 0.5) times weaker than the last.
+        octaves: Option<u32>,
+        lacunarity: Option<f32>,
+        gain: Option<f32>,
+    },
+    Turbulence {
+        colors: Option<[[f32; 3]; 2]>,
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+        octaves: Option<u32>,
+        lacunarity: Option<f32>,
+        gain: Option<f32>,
+    },
+    Marble {
+        colors: Option<[[f32; 3]; 2]>,
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+        /// Octaves of turbulence (default 5) and how far it bends the veins
+        /// (default 4).
+        octaves: Option<u32>,
+        strength: Option<f32>,
+    },
+    Wood {
+        colors: Option<[[f32; 3]; 2]>,
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+        /// Rings per unit of radius (default 4) and their noise wobble
+        /// (default 0.2).
+        rings: Option<f32>,
+        strength: Option<f32>,
+    },
+    Voronoi {
+        colors: Option<[[f32; 3]; 2]>,
+        scale: Option<f32>,
+        space: Option<TextureSpaceFile>,
+        /// Random displacement of the cell points, 0 to 1 (default 1).
+        jitter: Option<f32>,
+    },
+    Image {
+        /// PNG or Radiance HDR file, relative to the scene file.
+        path: PathBuf,
+        /// Decode values from sRGB to linear; defaults to true for PNG files
+        /// unless the texture is a normal or bump map. Turn off for other
+        /// data such as roughness maps.
+        srgb: Option<bool>,
+        /// "repeat" (default), "mirror" or "clamp".
+        wrap: Option<WrapFile>,
+        /// "trilinear" (default) or "bilinear".
+        filter: Option<TextureFilterFile>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TextureSpaceFile {
+    World,
+    Object,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WrapFile {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TextureFilterFile {
+    Bilinear,
+    Trilinear,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereFile {
+    center: [f32; 3],
+    radius: f32,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshFile {
+    /// OBJ file, relative to the scene file.
+    path: PathBuf,
+    /// Overrides the materials from the OBJ's MTL library.
+    material: Option<String>,
+    scale: Option<f32>,
+    position: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaneFile {
+    center: [f32; 3],
+    normal: [f32; 3],
+    /// Direction of the first extent axis; derived from the normal if omitted.
+    tangent: Option<[f32; 3]>,
+    /// Half sizes along the tangent and bitangent; the plane is infinite without it.
+    extent: Option<[f32; 2]>,
+    material: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightFile {
+    /// Required for all but directional lights.
+    position: Option<[f32; 3]>,
+    intensity: f32,
+    /// RGB colour multiplying the intensity; white by default.
+    color: Option<[f32; 3]>,
+    /// "point" (default), "sphere", "rect", "disk" or "directional".
+    shape: Option<LightShapeFile>,
+    /// Direction a directional light shines in, or the axis of a spotlight.
+    direction: Option<[f32; 3]>,
+    /// Spotlight half-angle in degrees at which the light starts to fade;
+    /// defaults to `outer_angle`.
+    inner_angle: Option<f32>,
+    /// Spotlight half-angle in degrees beyond which there is no light. Setting
+    /// it turns any non-directional light into a spotlight.
+    outer_angle: Option<f32>,
+    /// "none" (default), "inverse_square", or a table with `constant`, `linear`
+    /// and `quadratic` coefficients.
+    falloff: Option<FalloffFile>,
+    /// Radius of sphere and disk lights.
+    radius: Option<f32>,
+    /// Emitting side of rect and disk lights.
+    normal: Option<[f32; 3]>,
+    /// Direction of a rect light's first extent axis.
+    tangent: Option<[f32; 3]>,
+    /// Half sizes of a rect light along the tangent and bitangent.
+    extent: Option<[f32; 2]>,
+    /// Shadow rays per shading point, overriding the render setting.
+    samples: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LightShapeFile {
+    Point,
+    Sphere,
+    Rect,
+    Disk,
+    Directional,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FalloffFile {
+    Named(FalloffName),
+    Custom {
+        #[serde(default)]
+        constant: f32,
+        #[serde(default)]
+        linear: f32,
+        #[serde(default)]
+        quadratic: f32,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FalloffName {
+    None,
+    InverseSquare,
+}
+
+fn light(index: usize, l: &LightFile) -> Result<Light, SceneError> {
+    let invalid = |message| SceneError::InvalidLight { index, message };
+    let normal = || {
+        let normal = l.normal.ok_or_else(|| invalid("missing normal"))?;
+        unit(normal).ok_or_else(|| invalid("normal must be nonzero"))
+    };
+    let radius = || match l.radius {
+        Some(radius) if radius > 0.0 => Ok(radius),
+        Some(_) => Err(invalid("radius must be positive")),
+        None => Err(invalid("missing radius")),
+    };
+    let direction = || {
+        let direction = l.direction.ok_or_else(|| invalid("missing direction"))?;
+        unit(direction).ok_or_else(|| invalid("direction must be nonzero"))
+    };
+    let shape = match l.shape {
+        None | Some(LightShapeFile::Point) => LightShape::Point,
+        Some(LightShapeFile::Sphere) => LightShape::Sphere { radius: radius()? },
+        Some(LightShapeFile::Disk) => LightShape::Disk {
+            normal: normal()?,
+            radius: radius()?,
+        },
+        Some(LightShapeFile::Rect) => {
+            let extent = l.extent.ok_or_else(|| invalid("missing extent"))?;
+            if extent.iter().any(|e| *e <= 0.0 || e.is_nan()) {
+                return Err(invalid("extent must be positive"));
+            }
+            let tangent = l
+                .tangent
+                .map(vec3)
+                .unwrap_or_else(|| Vec3::new(0.0, 0.0, 0.0));
+            let frame = Plane::new(Vec3::new(0.0, 0.0, 0.0), normal()?, tangent, None, 0);
+            LightShape::Rect {
+                normal: frame.normal,
+                u: frame.tangent * extent[0],
+                v: frame.bitangent() * extent[1],
+            }
+        }
+        Some(LightShapeFile::Directional) => LightShape::Directional {
+            direction: direction()?,
+        },
+    };
+    let directional = matches!(shape, LightShape::Directional { .. });
+    let position = match l.position {
+        Some(position) => vec3(position),
+        None if directional => Vec3::new(0.0, 0.0, 0.0),
+        None => return Err(invalid("missing position")),
+    };
+    let spot = match l.outer_angle {
+        Some(_) if directional => return Err(invalid("directional lights cannot be spotlights")),
+        Some(outer) => {
+            let inner = l.inner_angle.unwrap_or(outer);
+            if [inner, outer].iter().any(|a| !(0.0..=180.0).contains(a)) {
+                return Err(invalid("spot angles must be between 0 and 180 degrees"));
+            }
+            if inner > outer {
+                return Err(invalid("inner_angle must not exceed outer_angle"));
+            }
+            Some(Spot::new(
+                direction()?,
+                inner.to_radians(),
+                outer.to_radians(),
+            ))
+        }
+        None => None,
+    };
+    let falloff = match l.falloff {
+        None | Some(FalloffFile::Named(FalloffName::None)) => Falloff::None,
+        Some(FalloffFile::Named(FalloffName::InverseSquare)) => Falloff::InverseSquare,
+        Some(FalloffFile::Custom {
+            constant,
+            linear,
+            quadratic,
+        }) => {
+            let coefficients = [constant, linear, quadratic];
+            if coefficients.iter().any(|c| *c < 0.0 || c.is_nan()) {
+                return Err(invalid("falloff coefficients must not be negative"));
+            }
+            if coefficients.iter().all(|c| *c == 0.0) {
+                return Err(invalid("falloff coefficients are all zero"));
+            }
+            Falloff::Custom {
+                constant,
+                linear,
+                quadratic,
+            }
+        }
+    };
+    Ok(Light {
+        position,
+        intensity: l.intensity,
+        color: l
+            .color
+            .map(vec3)
+            .unwrap_or_else(|| Vec3::new(1.0, 1.0, 1.0)),
+        shape,
+        falloff,
+        spot,
+        samples: l.samples,
+    })
+}
+
+fn material(
+    name: &str,
+    m: &MaterialFile,
+    texture_ids: &HashMap<String, usize>,
+) -> Result<Material, SceneError> {
+    let invalid = |message| SceneError::InvalidMaterial {
+        name: name.to_string(),
+        message,
+    };
+    let texture = |slot: &Option<String>| match slot {
+        Some(texture) => match texture_ids.get(texture) {
+            Some(&id) => Ok(Some(id)),
+            None => Err(SceneError::UnknownTexture {
+                material: name.to_string(),
+                name: texture.clone(),
+            }),
+        },
+        None => Ok(None),
+    };
+    let diffuse_texture = texture(&m.diffuse_texture)?;
+    let roughness_texture = texture(&m.roughness_texture)?;
+    let metallic_texture = texture(&m.metallic_texture)?;
+    let transmission_texture = texture(&m.transmission_texture)?;
+    let emission_texture = texture(&m.emission_texture)?;
+    let normal_texture = texture(&m.normal_texture)?;
+    let bump_texture = texture(&m.bump_texture)?;
+    let bump_scale = m.bump_scale.unwrap_or(1.0);
+    let is_pbr = m.base_color.is_some()
+        || m.metallic.is_some()
+        || m.roughness.is_some()
+        || m.specular.is_some()
+        || m.transmission.is_some()
+        || roughness_texture.is_some()
+        || metallic_texture.is_some()
+        || transmission_texture.is_some();
+    if !is_pbr {
+        let albedo = m.albedo.ok_or_else(|| invalid("missing albedo"))?;
+        let specular_exponent = m
+            .specular_exponent
+            .ok_or_else(|| invalid("missing specular_exponent"))?;
+        return Ok(Material {
+            refractive_index: m.refractive_index.unwrap_or(1.0),
+            diffuse_color: vec3(m.diffuse_color),
+            diffuse_texture,
+            albedo: Vec4::new(albedo[0], albedo[1], albedo[2], albedo[3]),
+            specular_exponent,
+            emission: vec3(m.emission),
+            roughness_texture,
+            metallic_texture,
+            transmission_texture,
+            emission_texture,
+            normal_texture,
+            bump_texture,
+            bump_scale,
+            pbr: None,
+        });
+    }
+    if m.albedo.is_some() || m.specular_exponent.is_some() {
+        return Err(invalid("mixes Phong and PBR parameters"));
+    }
+    let defaults = Pbr::default();
+    let ior = match (m.refractive_index, m.specular) {
+        (Some(_), Some(_)) => return Err(invalid("sets both refractive_index and specular")),
+        (Some(ior), None) => ior,
+        (None, Some(specular)) => Pbr::ior_from_specular(specular),
+        (None, None) => defaults.ior,
+    };
+    let pbr = Pbr {
+        base_color: m.base_color.map(vec3).unwrap_or(defaults.base_color),
+        metallic: m.metallic.unwrap_or(defaults.metallic).clamp(0.0, 1.0),
+        roughness: m.roughness.unwrap_or(defaults.roughness).clamp(0.0, 1.0),
+        ior,
+        transmission: m.transmission.unwrap_or(0.0).clamp(0.0, 1.0),
+    };
+    Ok(Material {
+        refractive_index: ior,
+        diffuse_color: pbr.base_color,
+        diffuse_texture,
+        emission: vec3(m.emission),
+        roughness_texture,
+        metallic_texture,
+        transmission_texture,
+        emission_texture,
+        normal_texture,
+        bump_texture,
+        bump_scale,
+        pbr: Some(pbr),
+        ..Material::default()
+    })
+}
+
+fn vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+/// `v` normalised, or `None` if it is zero or not finite.
+fn unit(v: [f32; 3]) -> Option<Vec3> {
+    let v = vec3(v);
+    let norm = v.norm();
+    if norm > 0.0 && norm.is_finite() {
+        Some(v / norm)
+    } else {
+        None
+    }
+}
+
+fn find_material<F>(
+    materials: &HashMap<String, usize>,
+    name: &str,
+    object: F,
+) -> Result<usize, SceneError>
+where
+    F: FnOnce() -> String,
+{
+    match materials.get(name) {
+        Some(&id) => Ok(id),
+        None => Err(SceneError::UnknownMaterial {
+            object: object(),
+            name: name.to_string(),
+        }),
+    }
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"))
+}
+
+fn procedural(
+    pattern: Pattern,
+    colors: Option<[[f32; 3]; 2]>,
+    scale: Option<f32>,
+    space: Option<TextureSpaceFile>,
+) -> Texture {
+    let [c0, c1] = colors.unwrap_or([[0.0; 3], [1.0; 3]]);
+    Texture::Procedural(Procedural {
+        pattern,
+        colors: [vec3(c0), vec3(c1)],
+        scale: scale.unwrap_or(1.0),
+        space: match space {
+            None | Some(TextureSpaceFile::World) => TextureSpace::World,
+            Some(TextureSpaceFile::Object) => TextureSpace::Object,
+        },
+    })
+}
+
+/// Parses a TOML scene description. Image size in the file overrides the
+/// default render settings; mesh paths are resolved relative to `base_dir`.
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<(Scene, RenderSettings), SceneError> {
+    let file: SceneFile = toml::from_str(source)?;
+
+    // Normal and bump maps hold vectors and heights rather than colours, so
+    // they are never decoded from sRGB. Maps each to a material using it.
+    let mut data_textures: HashMap<&str, &str> = HashMap::new();
+    for (material, m) in &file.materials {
+        for texture in m.normal_texture.iter().chain(&m.bump_texture) {
+            data_textures.insert(texture, material);
+        }
+    }
+
+    let mut textures = vec![];
+    let mut texture_ids: HashMap<String, usize> = HashMap::new();
+    for (name, t) in file.textures {
+        let texture = match t {
+            TextureFile::Checker {
+                even,
+                odd,
+                scale,
+                space,
+            } => procedural(Pattern::Checker, Some([even, odd]), scale, space),
+            TextureFile::Stripes {
+                colors,
+                scale,
+                space,
+                axis,
+            } => {
+                let axis = axis.map_or(Vec3::new(1.0, 0.0, 0.0), vec3);
+                procedural(Pattern::Stripes { axis }, colors, scale, space)
+            }
+            TextureFile::Noise {
+                colors,
+                scale,
+                space,
+            } => procedural(Pattern::Noise, colors, scale, space),
+            TextureFile::Fbm {
+                colors,
+                scale,
+                space,
+                octaves,
+                lacunarity,
+                gain,
+            } => {
+                let pattern = Pattern::Fbm {
+                    octaves: octaves.unwrap_or(5),
+                    lacunarity: lacunarity.unwrap_or(2.0),
+                    gain: gain.unwrap_or(0.5),
+                };
+                procedural(pattern, colors, scale, space)
+            }
+            TextureFile::Turbulence {
+                colors,
+                scale,
+                space,
+                octaves,
+                lacunarity,
+                gain,
+            } => {
+                let pattern = Pattern::Turbulence {
+                    octaves: octaves.unwrap_or(5),
+                    lacunarity: lacunarity.unwrap_or(2.0),
+                    gain: gain.unwrap_or(0.5),
+                };
+                procedural(pattern, colors, scale, space)
+            }
+            TextureFile::Marble {
+                colors,
+                scale,
+                space,
+                octaves,
+                strength,
+            } => {
+                let pattern = Pattern::Marble {
+                    octaves: octaves.unwrap_or(5),
+                    strength: strength.unwrap_or(4.0),
+                };
+                procedural(pattern, colors, scale, space)
+            }
+            TextureFile::Wood {
+                colors,
+                scale,
+                space,
+                rings,
+                strength,
+            } => {
+                let pattern = Pattern::Wood {
+                    rings: rings.unwrap_or(4.0),
+                    strength: strength.unwrap_or(0.2),
+                };
+                procedural(pattern, colors, scale, space)
+            }
+            TextureFile::Voronoi {
+                colors,
+                scale,
+                space,
+                jitter,
+            } => {
+                let jitter = jitter.unwrap_or(1.0).clamp(0.0, 1.0);
+                procedural(Pattern::Voronoi { jitter }, colors, scale, space)
+            }
+            TextureFile::Image {
+                path,
+                srgb,
+                wrap,
+                filter,
+            } => {
+                let path = base_dir.join(&path);
+                let srgb = match data_textures.get(name.as_str()) {
+                    Some(material) if srgb == Some(true) => {
+                        return Err(SceneError::InvalidMaterial {
+                            name: material.to_string(),
+                            message: "normal and bump maps cannot be sRGB-encoded",
+                        })
+                    }
+                    Some(_) => false,
+                    None => srgb.unwrap_or_else(|| is_png(&path)),
+                };
+                let mut image =
+                    Image::load(&path).map_err(|e| SceneError::Image(path.clone(), e))?;
+                if srgb {
+                    image = image.srgb_to_linear();
+                }
+                let wrap = match wrap {
+                    None | Some(WrapFile::Repeat) => Wrap::Repeat,
+                    Some(WrapFile::Mirror) => Wrap::Mirror,
+                    Some(WrapFile::Clamp) => Wrap::Clamp,
+                };
+                let filter = match filter {
+                    None | Some(TextureFilterFile::Trilinear) => TextureFilter::Trilinear,
+                    Some(TextureFilterFile::Bilinear) => TextureFilter::Bilinear,
+                };
+                Texture::Image(ImageTexture::new(image, wrap, filter))
+            }
+        };
+        texture_ids.insert(name, textures.len());
+        textures.push(texture);
+    }
+
+    let mut scene = Scene {
+        textures,
+        ..Scene::default()
+    };
+
+    let mut materials: HashMap<String, usize> = HashMap::new();
+    for (name, m) in file.materials {
+        let material = material(&name, &m, &texture_ids)?;
+        materials.insert(name, scene.add_material(material));
+    }
+
+    for (index, s) in file.spheres.into_iter().enumerate() {
+        let material_id = find_material(&materials, &s.material, || format!("sphere #{}", index))?;
+        if s.radius <= 0.0 || s.radius.is_nan() {
+            return Err(SceneError::InvalidShape {
+                object: format!("sphere #{}", index),
+                message: "radius must be positive",
+            });
+        }
+        scene.add(Sphere {
+            center: vec3(s.center),
+            radius: s.radius,
+            material_id,
+        });
+    }
+
+    for (index, m) in file.meshes.into_iter().enumerate() {
+        let scale = m.scale.unwrap_or(1.0);
+        if scale == 0.0 || scale.is_nan() {
+            return Err(SceneError::InvalidShape {
+                object: format!("mesh #{}", index),
+                message: "scale must be nonzero",
+            });
+        }
+        let (mut mesh, mesh_materials) = load_obj(&base_dir.join(&m.path))?;
+        let ids: Vec<usize> = match &m.material {
+            Some(name) => {
+                let id = find_material(&materials, name, || format!("mesh #{}", index))?;
+                vec![id; mesh_materials.len()]
+            }
+            None => mesh_materials
+                .into_iter()
+                .map(|material| scene.add_material(material))
+                .collect(),
+        };
+        for triangle in &mut mesh.triangles {
+            triangle.material_id = ids[triangle.material_id];
+        }
+        let offset = m
+            .position
+            .map(vec3)
+            .unwrap_or_else(|| Vec3::new(0.0, 0.0, 0.0));
+        mesh.transform(scale, offset);
+        scene.add(mesh);
+    }
+
+    for (index, p) in file.planes.into_iter().enumerate() {
+        let material_id = find_material(&materials, &p.material, || format!("plane #{}", index))?;
+        let normal = vec3(p.normal);
+        if normal.norm() == 0.0 || normal.iter().any(|c| c.is_nan()) {
+            return Err(SceneError::InvalidShape {
+                object: format!("plane #{}", index),
+                message: "normal must be nonzero",
+            });
+        }
+        scene.add(Plane::new(
+            vec3(p.center),
+            normal,
+            p.tangent
+                .map(vec3)
+                .unwrap_or_else(|| Vec3::new(0.0, 0.0, 0.0)),
+            p.extent.map(|e| Vec2::new(e[0], e[1])),
+            material_id,
+        ));
+    }
+
+    scene.lights = file
+        .lights
+        .iter()
+        .enumerate()
+        .map(|(index, l)| light(index, l))
+        .collect::<Result<_, _>>()?;
+
+    if let Some(position) = file.camera.position {
+        scene.camera.position = vec3(position);
+    }
+    // Without a target the camera keeps looking down -z.
+    scene.camera.target = match file.camera.target {
+        Some(target) => vec3(target),
+        None => scene.camera.position + Vec3::new(0.0, 0.0, -1.0),
+    };
+    if let Some(up) = file.camera.up {
+        scene.camera.up = vec3(up);
+    }
+    if let Some(fov) = file.camera.fov {
+        scene.camera.fov = fov.to_radians();
+    }
+    if let Some(axis) = file.camera.fov_axis {
+        scene.camera.fov_axis = match axis {
+            FovAxisFile::Vertical => FovAxis::Vertical,
+            FovAxisFile::Horizontal => FovAxis::Horizontal,
+        };
+    }
+    if let Some(projection) = file.camera.projection {
+        scene.camera.projection = match projection {
+            ProjectionFile::Perspective => Projection::Perspective,
+            ProjectionFile::Orthographic => Projection::Orthographic {
+                size: file.camera.size.unwrap_or(2.0),
+            },
+            ProjectionFile::Fisheye => Projection::Fisheye,
+            ProjectionFile::Equirectangular => Projection::Equirectangular,
+        };
+    }
+    if let Some(aperture) = file.camera.aperture {
+        scene.camera.aperture = aperture;
+    }
+    scene.camera.focus_distance = file.camera.focus_distance;
+    if let Some(blades) = file.camera.blades {
+        scene.camera.blades = blades;
+    }
+    if let Some(rotation) = file.camera.blade_rotation {
+        scene.camera.blade_rotation = rotation.to_radians();
+    }
+    scene
+        .camera
+        .validate()
+        .map_err(|message| SceneError::InvalidCamera { message })?;
+    if let Some(background) = file.background {
+        scene.background = vec3(background);
+    }
+    if let Some(e) = file.environment {
+        let path = base_dir.join(&e.path);
+        let mut image = Image::load(&path).map_err(|e| SceneError::Image(path.clone(), e))?;
+        if e.srgb.unwrap_or_else(|| is_png(&path)) {
+            image = image.srgb_to_linear();
+        }
+        scene.environment = Some(Environment::new(
+            image,
+            e.rotation.unwrap_or(0.0).to_radians(),
+            e.intensity.unwrap_or(1.0),
+        ));
+    }
+
+    let mut settings = RenderSettings::default();
+    if let Some(width) = file.width {
+        settings.width = width;
+    }
+    if let Some(height) = file.height {
+        settings.height = height;
+    }
+    if settings.width == 0 || settings.height == 0 {
+        return Err(SceneError::InvalidSize {
+            width: settings.width,
+            height: settings.height,
+        });
+    }
+    Ok((scene, settings))
+}
+
+pub fn load_scene(path: &Path) -> Result<(Scene, RenderSettings), SceneError> {
+    let source = fs::read_to_string(path)?;
+    parse_scene(&source, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<(Scene, RenderSettings), SceneError> {
+        parse_scene(source, Path::new("."))
+    }
+
+    #[test]
+    fn loads_a_minimal_scene() {
+        let (scene, _) = parse(
+            r#"
+            [materials.red]
+            refractive_index = 1.0
+            albedo = [1.0, 0.0, 0.0, 0.0]
+            diffuse_color = [1.0, 0.0, 0.0]
+            specular_exponent = 10.0
+
+            [[spheres]]
+            center = [0.0, 0.0, -4.0]
+            radius = 1.0
+            material = "red"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scene.camera.target, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn rejects_an_unknown_material() {
+        let result = parse(
+            r#"
+            [[spheres]]
+            center = [0.0, 0.0, -4.0]
+            radius = 1.0
+            material = "missing"
+            "#,
+        );
+        match result {
+            Err(SceneError::UnknownMaterial { name, .. }) => assert_eq!(name, "missing"),
+            other => panic!("expected an unknown material, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn rejects_an_invalid_camera() {
+        let cameras = [
+            (
+                "position = [1.0, 2.0, 3.0]\ntarget = [1.0, 2.0, 3.0]",
+                "target must differ from position",
+            ),
+            ("fov = 0.0", "fov must be positive"),
+            ("fov = -30.0", "fov must be positive"),
+            ("fov = 180.0", "perspective fov must be below 180 degrees"),
+            (
+                "projection = \"fisheye\"\nfov = 400.0",
+                "fisheye fov must not exceed 360 degrees",
+            ),
+            (
+                "projection = \"orthographic\"\nsize = 0.0",
+                "size must be positive",
+            ),
+        ];
+        for (camera, expected) in &cameras {
+            match parse(&format!("[camera]\n{}\n", camera)) {
+                Err(SceneError::InvalidCamera { message }) => assert_eq!(message, *expected),
+                other => panic!(
+                    "{}: expected an invalid camera, got {:?}",
+                    camera,
+                    other.err()
+                ),
+            }
+        }
+    }
+
+    /// Scene with an image texture `tiles`, bound to `slot` of a material, and
+    /// the texture's `srgb` setting if any.
+    fn textured(slot: &str, srgb: &str) -> Result<(Scene, RenderSettings), SceneError> {
+        parse_scene(
+            &format!(
+                r#"
+                [textures.tiles]
+                type = "image"
+                path = "tiles_normal.png"
+                {}
+
+                [materials.tiled]
+                refractive_index = 1.0
+                albedo = [1.0, 0.0, 0.0, 0.0]
+                specular_exponent = 10.0
+                {} = "tiles"
+                "#,
+                srgb, slot
+            ),
+            Path::new("scenes"),
+        )
+    }
+
+    fn texel(scene: &Scene) -> Vec3 {
+        let zero = Vec2::new(0.0, 0.0);
+        match &scene.textures[0] {
+            Texture::Image(texture) => texture.lookup(&Vec2::new(0.3, 0.6), &zero, &zero),
+            Texture::Procedural(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn normal_and_bump_maps_load_linear() {
+        let linear = texel(&textured("diffuse_texture", "srgb = false").unwrap().0);
+        let decoded = texel(&textured("diffuse_texture", "").unwrap().0);
+        assert!((linear - decoded).norm() > 1e-3);
+        for slot in &["normal_texture", "bump_texture"] {
+            assert_eq!(texel(&textured(slot, "").unwrap().0), linear);
+            assert_eq!(texel(&textured(slot, "srgb = false").unwrap().0), linear);
+            match textured(slot, "srgb = true") {
+                Err(SceneError::InvalidMaterial { name, .. }) => assert_eq!(name, "tiled"),
+                other => panic!(
+                    "{}: expected an invalid material, got {:?}",
+                    slot,
+                    other.err()
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::bvh::{Aabb, Bvh};
+use crate::sampling::orthonormal_basis;
+use crate::shape::{unit_or_zero, Hit, Shape};
+use crate::{Vec2, Vec3};
+
+#[derive(Clone, Copy)]
+pub struct Triangle {
+    /// Indices into `Mesh::positions`.
+    pub vertices: [usize; 3],
+    /// Indices into `Mesh::normals`; the geometric normal is used when absent.
+    pub normals: Option<[usize; 3]>,
+    /// Indices into `Mesh::uvs`.
+    pub uvs: Option<[usize; 3]>,
+    /// Index into `Scene::materials`.
+    pub material_id: usize,
+}
+
+/// Indexed triangle mesh with optional per-vertex normals and texture
+/// coordinates. Triangles are kept in their own BVH, so a mesh is a single
+/// shape as far as the scene is concerned.
+#[derive(Clone)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Vec2>,
+    pub triangles: Vec<Triangle>,
+    /// Accumulated `transform`, undone to give hits their object-space
+    /// position.
+    scale: f32,
+    offset: Vec3,
+    /// Per-position average of the unit u tangents of the textured triangles
+    /// around it.
+    tangents: Vec<Vec3>,
+    /// Running total of triangle areas, for sampling points on the surface.
+    cumulative_areas: Vec<f32>,
+    bvh: Bvh,
+    bounds: Aabb,
+}
+
+/// Möller–Trumbore ray/triangle test. Returns the distance along the ray and
+/// the barycentric coordinates `(u, v)` of the hit relative to `p1` and `p2`.
+/// Tolerances scale with the triangle and its distance from the origin, so
+/// tiny meshes are not lost.
+pub fn intersect_triangle(
+    orig: &Vec3,
+    dir: &Vec3,
+    p0: &Vec3,
+    p1: &Vec3,
+    p2: &Vec3,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = dir.cross(&edge2);
+    let det = edge1.dot(&pvec);
+    // det is |dir| |edge1 x edge2| times the cosine of the incidence angle.
+    if det.abs() <= 1e-7 * dir.norm() * edge1.cross(&edge2).norm() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = orig - p0;
+    let u = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let qvec = tvec.cross(&edge1);
+    let v = dir.dot(&qvec) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(&qvec) * inv_det;
+    // Skip hits within rounding error of the origin.
+    if t * dir.norm() > 1e-6 * (orig.norm() + p0.norm()) {
+        Some((t, u, v))
+    } else {
+        None
+    }
+}
+
+impl Mesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<Vec2>,
+        triangles: Vec<Triangle>,
+    ) -> Mesh {
+        let mut mesh = Mesh {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            scale: 1.0,
+            offset: Vec3::zeros(),
+            tangents: vec![],
+            cumulative_areas: vec![],
+            bvh: Bvh::build(&[]),
+            bounds: Aabb::empty(),
+        };
+        mesh.rebuild();
+        mesh
+    }
+
+    /// Rebuilds the triangle BVH, vertex tangents and area table; call after
+    /// editing `positions` or `triangles`.
+    pub fn rebuild(&mut self) {
+        let mut total = 0.0;
+        self.cumulative_areas = (0..self.triangles.len())
+            .map(|i| {
+                total += self.triangle_area(i);
+                total
+            })
+            .collect();
+        let mut tangents = vec![Vec3::zeros(); self.positions.len()];
+        for (i, triangle) in self.triangles.iter().enumerate() {
+            if triangle.uvs.is_some() {
+                let tangent = unit_or_zero(&self.uv_derivatives(i).0);
+                for &v in &triangle.vertices {
+                    tangents[v] += tangent;
+                }
+            }
+        }
+        self.tangents = tangents.iter().map(unit_or_zero).collect();
+        let bounds: Vec<Aabb> = (0..self.triangles.len())
+            .map(|i| self.triangle_bounds(i))
+            .collect();
+        self.bounds = bounds.iter().fold(Aabb::empty(), |acc, b| acc.union(b));
+        self.bvh = Bvh::build(&bounds);
+    }
+
+    pub fn triangle_positions(&self, index: usize) -> [Vec3; 3] {
+        let v = self.triangles[index].vertices;
+        [
+            self.positions[v[0]],
+            self.positions[v[1]],
+            self.positions[v[2]],
+        ]
+    }
+
+    pub fn triangle_bounds(&self, index: usize) -> Aabb {
+        let [p0, p1, p2] = self.triangle_positions(index);
+        Aabb::empty().grow(&p0).grow(&p1).grow(&p2)
+    }
+
+    /// Distance and barycentrics of the hit with triangle `index`, if any.
+    pub fn ray_intersect(&self, index: usize, orig: &Vec3, dir: &Vec3) -> Option<(f32, f32, f32)> {
+        let [p0, p1, p2] = self.triangle_positions(index);
+        intersect_triangle(orig, dir, &p0, &p1, &p2)
+    }
+
+    /// Interpolated shading normal and texture coordinates at barycentrics `(u, v)`.
+    pub fn surface(&self, index: usize, u: f32, v: f32) -> (Vec3, Vec2) {
+        let triangle = &self.triangles[index];
+        let w = 1.0 - u - v;
+        let normal = match triangle.normals {
+            Some(n) => (self.normals[n[0]] * w + self.normals[n[1]] * u + self.normals[n[2]] * v)
+                .normalize(),
+            None => {
+                let [p0, p1, p2] = self.triangle_positions(index);
+                (p1 - p0).cross(&(p2 - p0)).normalize()
+            }
+        };
+        let uv = match triangle.uvs {
+            Some(t) => self.uvs[t[0]] * w + self.uvs[t[1]] * u + self.uvs[t[2]] * v,
+            None => Vec2::new(u, v),
+        };
+        (normal, uv)
+    }
+
+    /// Derivatives of the position with respect to the texture coordinates of
+    /// triangle `index`, which default to its barycentrics when it has none.
+    pub fn uv_derivatives(&self, index: usize) -> (Vec3, Vec3) {
+        let triangle = &self.triangles[index];
+        let [p0, p1, p2] = self.triangle_positions(index);
+        let [uv0, uv1, uv2] = match triangle.uvs {
+            Some(t) => [self.uvs[t[0]], self.uvs[t[1]], self.uvs[t[2]]],
+            None => [
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(0.0, 1.0),
+            ],
+        };
+        let (duv02, duv12) = (uv0 - uv2, uv1 - uv2);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+        let det = duv02.x * duv12.y - duv02.y * duv12.x;
+        if det.abs() < 1e-12 {
+            // Degenerate mapping: any frame in the triangle's plane will do.
+            return orthonormal_basis(&(p1 - p0).cross(&(p2 - p0)).normalize());
+        }
+        let inv = 1.0 / det;
+        (
+            (dp02 * duv12.y - dp12 * duv02.y) * inv,
+            (dp12 * duv02.x - dp02 * duv12.x) * inv,
+        )
+    }
+
+    pub fn triangle_area(&self, index: usize) -> f32 {
+        let [p0, p1, p2] = self.triangle_positions(index);
+        0.5 * (p1 - p0).cross(&(p2 - p0)).norm()
+    }
+
+    /// Hit record for the point at barycentrics `(u, v)` of triangle `index`.
+    fn hit_at(&self, index: usize, u: f32, v: f32, t: f32) -> Hit {
+        let [p0, p1, p2] = self.triangle_positions(index);
+        let point = p0 * (1.0 - u - v) + p1 * u + p2 * v;
+        let (normal, uv) = self.surface(index, u, v);
+        let (dpdu, dpdv) = self.uv_derivatives(index);
+        Hit {
+            t,
+            point,
+            local: (point - self.offset) / self.scale,
+            normal,
+            uv,
+            dpdu,
+            dpdv,
+            tangent: self.shading_tangent(index, u, v, &dpdu),
+            duvdx: Vec2::zeros(),
+            duvdy: Vec2::zeros(),
+            material_id: self.triangles[index].material_id,
+        }
+    }
+
+    /// Tangent for normal maps at barycentrics `(u, v)`: interpolated from the
+    /// vertices for smooth triangles, along `dpdu` for flat ones or where the
+    /// vertex tangents cancel out.
+    fn shading_tangent(&self, index: usize, u: f32, v: f32, dpdu: &Vec3) -> Vec3 {
+        let triangle = &self.triangles[index];
+        if triangle.normals.is_some() && triangle.uvs.is_some() {
+            let [t0, t1, t2] = triangle.vertices.map(|i| self.tangents[i]);
+            let tangent = t0 * (1.0 - u - v) + t1 * u + t2 * v;
+            if tangent.norm() > 1e-3 {
+                return tangent.normalize();
+            }
+        }
+        unit_or_zero(dpdu)
+    }
+
+    /// Scales the mesh uniformly about the origin, then moves it by `offset`.
+    pub fn transform(&mut self, scale: f32, offset: Vec3) {
+        for p in &mut self.positions {
+            *p = *p * scale + offset;
+        }
+        self.scale *= scale;
+        self.offset = self.offset * scale + offset;
+        if scale < 0.0 {
+            for n in &mut self.normals {
+                *n = -*n;
+            }
+        }
+        self.rebuild();
+    }
+}
+
+impl Shape for Mesh {
+    fn intersect(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> Option<Hit> {
+        let mut barycentrics = (0.0, 0.0);
+        let (index, t) = self.bvh.intersect(orig, dir, t_max, |i, t_max| {
+            let (t, u, v) = self.ray_intersect(i, orig, dir)?;
+            if t < t_max {
+                barycentrics = (u, v);
+                Some(t)
+            } else {
+                None
+            }
+        })?;
+        let mut hit = self.hit_at(index, barycentrics.0, barycentrics.1, t);
+        // The ray's own point is closer than the barycentric one to where the
+        // ray actually is.
+        hit.point = orig + dir * t;
+        hit.local = (hit.point - self.offset) / self.scale;
+        Some(hit)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        if self.triangles.is_empty() {
+            None
+        } else {
+            Some(self.bounds)
+        }
+    }
+
+    fn occluded(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> bool {
+        self.bvh.occluded(orig, dir, t_max, |i, t_max| {
+            self.ray_intersect(i, orig, dir)
+                .is_some_and(|(t, _, _)| t < t_max)
+        })
+    }
+
+    /// Picks a triangle in proportion to its area with `u.x`, then a uniform
+    /// point in it.
+    fn sample_surface(&self, u: &Vec2) -> Option<Hit> {
+        let total = *self.cumulative_areas.last()?;
+        if total <= 0.0 {
+            return None;
+        }
+        let target = u.x * total;
+        let index = self
+            .cumulative_areas
+            .partition_point(|&a| a <= target)
+            .min(self.triangles.len() - 1);
+        let start = if index == 0 {
+            0.0
+        } else {
+            self.cumulative_areas[index - 1]
+        };
+        let size = self.cumulative_areas[index] - start;
+        let s = ((target - start) / size).clamp(0.0, 1.0).sqrt();
+        Some(self.hit_at(index, s * (1.0 - u.y), s * u.y, 0.0))
+    }
+
+    fn area(&self) -> f32 {
+        self.cumulative_areas.last().copied().unwrap_or(0.0)
+    }
+
+    fn material_ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.triangles.iter().map(|t| t.material_id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_tiny_triangles() {
+        for &size in &[1.0, 1e-2, 1e-4, 1e-6] {
+            let p0 = Vec3::new(0.0, 0.0, 0.0);
+            let p1 = Vec3::new(size, 0.0, 0.0);
+            let p2 = Vec3::new(0.0, size, 0.0);
+            let orig = Vec3::new(0.25 * size, 0.25 * size, 1.0);
+            let dir = Vec3::new(0.0, 0.0, -1.0);
+            let (t, u, v) = intersect_triangle(&orig, &dir, &p0, &p1, &p2)
+                .unwrap_or_else(|| panic!("missed triangle of size {}", size));
+            assert!((t - 1.0).abs() < 1e-6);
+            assert!((u - 0.25).abs() < 1e-3 && (v - 0.25).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn misses_parallel_rays_and_hits_behind() {
+        let p0 = Vec3::new(0.0, 0.0, 0.0);
+        let p1 = Vec3::new(1.0, 0.0, 0.0);
+        let p2 = Vec3::new(0.0, 1.0, 0.0);
+        let orig = Vec3::new(0.2, 0.2, 1.0);
+        let parallel = Vec3::new(1.0, 0.0, 0.0);
+        assert!(intersect_triangle(&orig, &parallel, &p0, &p1, &p2).is_none());
+        let away = Vec3::new(0.0, 0.0, 1.0);
+        assert!(intersect_triangle(&orig, &away, &p0, &p1, &p2).is_none());
+        let degenerate = intersect_triangle(&orig, &-away, &p0, &p1, &p1);
+        assert!(degenerate.is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::Vec3;
+
+/// Integer hash of a lattice point, well mixed in all bits.
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^ (h >> 16)
+}
+
+/// Dot product of the offset `(x, y, z)` with one of the twelve cube-edge
+/// gradients picked by `h`, as in Perlin's improved noise.
+fn gradient(h: u32, x: f32, y: f32, z: f32) -> f32 {
+    match h % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// Perlin gradient noise, roughly in `[-1, 1]` and zero at lattice points.
+pub fn perlin(p: &Vec3) -> f32 {
+    let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (ix, iy, iz) = (fx as i32, fy as i32, fz as i32);
+    let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    let g = |dx: i32, dy: i32, dz: i32| {
+        gradient(
+            hash(ix + dx, iy + dy, iz + dz),
+            x - dx as f32,
+            y - dy as f32,
+            z - dz as f32,
+        )
+    };
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, g(0, 0, 0), g(1, 0, 0)),
+            lerp(u, g(0, 1, 0), g(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, g(0, 0, 1), g(1, 0, 1)),
+            lerp(u, g(0, 1, 1), g(1, 1, 1)),
+        ),
+    )
+}
+
+/// Fractional Brownian motion: `octaves` layers of noise, each `lacunarity`
+/// times the frequency and `gain` times the amplitude of the previous one.
+pub fn fbm(p: &Vec3, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    octaves_sum(p, octaves, lacunarity, gain, |n| n)
+}
+
+/// Like `fbm` but summing the absolute value of each octave, which gives
+/// sharp creases where the noise crosses zero.
+pub fn turbulence(p: &Vec3, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    octaves_sum(p, octaves, lacunarity, gain, f32::abs)
+}
+
+fn octaves_sum<F: Fn(f32) -> f32>(
+    p: &Vec3,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+    shape: F,
+) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves.max(1) {
+        sum += shape(perlin(&(p * frequency))) * amplitude;
+        frequency *= lacunarity;
+        amplitude *= gain;
+    }
+    sum
+}
+
+/// Cellular noise: distance from `p` to the nearest of one feature point per
+/// unit cell, each displaced from the cell centre by up to `jitter` of the
+/// cell size. Ranges over `[0, ~1]`.
+pub fn voronoi(p: &Vec3, jitter: f32) -> f32 {
+    let cell = p.map(f32::floor);
+    let mut nearest = f32::MAX;
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let c = cell + Vec3::new(dx as f32, dy as f32, dz as f32);
+                let h = hash(c.x as i32, c.y as i32, c.z as i32);
+                let offset = Vec3::new(
+                    unit(h),
+                    unit(h.wrapping_mul(0x2c1b_3c6d)),
+                    unit(h.wrapping_mul(0x297a_2d39)),
+                );
+                let feature = c
+                    + Vec3::new(0.5, 0.5, 0.5)
+                    + (offset * 2.0 - Vec3::new(1.0, 1.0, 1.0)) * (0.5 * jitter);
+                nearest = nearest.min((feature - p).norm());
+            }
+        }
+    }
+    nearest
+}
+
+/// Uniform value in `[0, 1)` from the high bits of a hash.
+fn unit(h: u32) -> f32 {
+    let h = hash(h as i32, 0x5bd1_e995, 0);
+    (h >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points scattered over a few cells on both sides of the origin.
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..4000).map(|i| {
+            let i = i as f32;
+            Vec3::new(
+                (i * 0.618_034).fract() * 8.0 - 4.0,
+                (i * 0.414_214).fract() * 8.0 - 4.0,
+                (i * 0.732_051).fract() * 8.0 - 4.0,
+            )
+        })
+    }
+
+    #[test]
+    fn noise_is_deterministic() {
+        for p in points().take(200) {
+            assert_eq!(perlin(&p), perlin(&p));
+            assert_eq!(fbm(&p, 5, 2.0, 0.5), fbm(&p, 5, 2.0, 0.5));
+            assert_eq!(turbulence(&p, 5, 2.0, 0.5), turbulence(&p, 5, 2.0, 0.5));
+            assert_eq!(voronoi(&p, 1.0), voronoi(&p, 1.0));
+        }
+    }
+
+    #[test]
+    fn perlin_is_zero_at_lattice_points() {
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in -3..3 {
+                    assert_eq!(perlin(&Vec3::new(x as f32, y as f32, z as f32)), 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let (mut min, mut max) = (0f32, 0f32);
+        for p in points() {
+            let n = perlin(&p);
+            assert!(n.abs() <= 1.05, "perlin {:?} = {}", p, n);
+            min = min.min(n);
+            max = max.max(n);
+            // Each octave is bounded like perlin, scaled by its amplitude.
+            let bound = 1.05 * (1.0 + 0.5 + 0.25 + 0.125);
+            let f = fbm(&p, 4, 2.0, 0.5);
+            assert!(f.abs() <= bound, "fbm {:?} = {}", p, f);
+            let t = turbulence(&p, 4, 2.0, 0.5);
+            assert!(t >= 0.0 && t <= bound, "turbulence {:?} = {}", p, t);
+            let v = voronoi(&p, 1.0);
+            assert!(v >= 0.0 && v <= 3f32.sqrt(), "voronoi {:?} = {}", p, v);
+        }
+        // The noise spans most of its range rather than hugging zero.
+        assert!(min < -0.5 && max > 0.5, "perlin spans [{}, {}]", min, max);
+    }
+
+    #[test]
+    fn voronoi_without_jitter_measures_to_cell_centres() {
+        for p in points().take(500) {
+            let centre = p.map(|c| c.floor() + 0.5);
+            assert!((voronoi(&p, 0.0) - (p - centre).norm()).abs() < 1e-5);
+        }
+    }
+}
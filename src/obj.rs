@@ -0,0 +1,349 @@
+use crate::mesh::{Mesh, Triangle};
+use crate::scene::Material;
+use crate::{Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+/// Material used for faces that have no `usemtl`, or name one the MTL files
+/// do not define.
+pub fn default_material() -> Material {
+    Material {
+        refractive_index: 1.0,
+        albedo: Vec4::new(0.9, 0.1, 0.0, 0.0),
+        diffuse_color: Vec3::new(0.8, 0.8, 0.8),
+        specular_exponent: 10.0,
+        ..Material::default()
+    }
+}
+
+fn parse_floats<'a, I>(fields: I, count: usize) -> Result<Vec<f32>, String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let values: Vec<f32> = fields
+        .take(count)
+        .map(|f| {
+            f.parse::<f32>()
+                .map_err(|_| format!("invalid number \"{}\"", f))
+        })
+        .collect::<Result<_, _>>()?;
+    if values.len() < count {
+        return Err(format!("expected {} numbers", count));
+    }
+    Ok(values)
+}
+
+/// Resolves a 1-based (or negative, relative) OBJ index against `len` elements.
+fn resolve_index(field: &str, len: usize) -> Result<usize, String> {
+    let index: i64 = field
+        .parse()
+        .map_err(|_| format!("invalid index \"{}\"", field))?;
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("index {} out of range", index));
+    }
+    Ok(resolved as usize)
+}
+
+/// Converts MTL parameters to the Phong-style `Material` used by the renderer.
+fn mtl_material(
+    kd: Vec3,
+    ks: Vec3,
+    ke: Vec3,
+    ns: f32,
+    ni: f32,
+    dissolve: f32,
+    illum: u32,
+) -> Material {
+    let specular = (ks.x + ks.y + ks.z) / 3.0;
+    let transparency = 1.0 - dissolve.clamp(0.0, 1.0);
+    let reflection = if illum >= 3 { specular } else { 0.0 };
+    Material {
+        refractive_index: ni,
+        albedo: Vec4::new(1.0 - transparency, specular, reflection, transparency),
+        diffuse_color: kd,
+        specular_exponent: ns,
+        emission: ke,
+        ..Material::default()
+    }
+}
+
+pub fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, Material>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<String> = None;
+    let mut kd = Vec3::new(0.8, 0.8, 0.8);
+    let mut ks = Vec3::new(0.0, 0.0, 0.0);
+    let mut ns = 10.0;
+    let mut ni = 1.0;
+    let mut dissolve = 1.0;
+    let mut illum = 2;
+    let mut ke = Vec3::new(0.0, 0.0, 0.0);
+
+    for (number, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: number + 1,
+            message,
+        };
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        match keyword {
+            "newmtl" => {
+                if let Some(name) = current.take() {
+                    materials.insert(name, mtl_material(kd, ks, ke, ns, ni, dissolve, illum));
+                }
+                let name = fields.collect::<Vec<_>>().join(" ");
+                if name.is_empty() {
+                    return Err(error("newmtl without a name".to_string()));
+                }
+                current = Some(name);
+                kd = Vec3::new(0.8, 0.8, 0.8);
+                ks = Vec3::new(0.0, 0.0, 0.0);
+                ns = 10.0;
+                ni = 1.0;
+                dissolve = 1.0;
+                illum = 2;
+                ke = Vec3::new(0.0, 0.0, 0.0);
+            }
+            "Kd" | "Ks" | "Ke" => {
+                let v = parse_floats(fields, 3).map_err(error)?;
+                let color = Vec3::new(v[0], v[1], v[2]);
+                match keyword {
+                    "Kd" => kd = color,
+                    "Ks" => ks = color,
+                    _ => ke = color,
+                }
+            }
+            "Ns" => ns = parse_floats(fields, 1).map_err(error)?[0],
+            "Ni" => ni = parse_floats(fields, 1).map_err(error)?[0],
+            "d" => dissolve = parse_floats(fields, 1).map_err(error)?[0],
+            "Tr" => dissolve = 1.0 - parse_floats(fields, 1).map_err(error)?[0],
+            "illum" => illum = parse_floats(fields, 1).map_err(error)?[0] as u32,
+            _ => {}
+        }
+    }
+    if let Some(name) = current {
+        materials.insert(name, mtl_material(kd, ks, ke, ns, ni, dissolve, illum));
+    }
+    Ok(materials)
+}
+
+/// Parses Wavefront OBJ geometry. Polygons are triangulated as fans; `mtllib`
+/// files are looked up relative to `path` and `usemtl` names mapped onto them.
+/// Triangle material ids index the returned materials, so callers adding the
+/// mesh to a scene must offset them by wherever those materials end up.
+pub fn parse_obj(source: &str, path: &Path) -> Result<(Mesh, Vec<Material>), ObjError> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut positions: Vec<Vec3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut uvs: Vec<Vec2> = vec![];
+    let mut triangles: Vec<Triangle> = vec![];
+    let mut materials = vec![default_material()];
+    let mut library: HashMap<String, Material> = HashMap::new();
+    let mut material_ids: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    for (number, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: number + 1,
+            message,
+        };
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        match keyword {
+            "v" => {
+                let v = parse_floats(fields, 3).map_err(error)?;
+                positions.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let v = parse_floats(fields, 3).map_err(error)?;
+                normals.push(Vec3::new(v[0], v[1], v[2]).normalize());
+            }
+            "vt" => {
+                let v = parse_floats(fields, 2).map_err(error)?;
+                uvs.push(Vec2::new(v[0], v[1]));
+            }
+            "f" => {
+                let mut corners = vec![];
+                for field in fields {
+                    let mut parts = field.split('/');
+                    let v = resolve_index(parts.next().unwrap_or(""), positions.len())
+                        .map_err(error)?;
+                    let vt = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(resolve_index(s, uvs.len()).map_err(error)?)
+                        }
+                        _ => None,
+                    };
+                    let vn = match parts.next() {
+                        Some(s) if !s.is_empty() => {
+                            Some(resolve_index(s, normals.len()).map_err(error)?)
+                        }
+                        _ => None,
+                    };
+                    corners.push((v, vt, vn));
+                }
+                if corners.len() < 3 {
+                    return Err(error("face with fewer than 3 vertices".to_string()));
+                }
+                for k in 1..corners.len() - 1 {
+                    let c = [corners[0], corners[k], corners[k + 1]];
+                    let corner_normals = match (c[0].2, c[1].2, c[2].2) {
+                        (Some(a), Some(b), Some(d)) => Some([a, b, d]),
+                        _ => None,
+                    };
+                    let corner_uvs = match (c[0].1, c[1].1, c[2].1) {
+                        (Some(a), Some(b), Some(d)) => Some([a, b, d]),
+                        _ => None,
+                    };
+                    triangles.push(Triangle {
+                        vertices: [c[0].0, c[1].0, c[2].0],
+                        normals: corner_normals,
+                        uvs: corner_uvs,
+                        material_id: current_material,
+                    });
+                }
+            }
+            "mtllib" => {
+                for name in fields {
+                    let mtl_path = dir.join(name);
+                    let source = fs::read_to_string(&mtl_path)
+                        .map_err(|e| ObjError::Io(mtl_path.clone(), e))?;
+                    library.extend(parse_mtl(&source, &mtl_path)?);
+                }
+            }
+            "usemtl" => {
+                let name = fields.collect::<Vec<_>>().join(" ");
+                current_material = match material_ids.get(&name) {
+                    Some(&id) => id,
+                    None => match library.get(&name) {
+                        Some(material) => {
+                            materials.push(*material);
+                            material_ids.insert(name, materials.len() - 1);
+                            materials.len() - 1
+                        }
+                        None => 0,
+                    },
+                };
+            }
+            _ => {}
+        }
+    }
+    Ok((Mesh::new(positions, normals, uvs, triangles), materials))
+}
+
+pub fn load_obj(path: &Path) -> Result<(Mesh, Vec<Material>), ObjError> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    parse_obj(&source, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<(Mesh, Vec<Material>), ObjError> {
+        parse_obj(source, Path::new("test.obj"))
+    }
+
+    const SQUARE: &str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+";
+
+    #[test]
+    fn quads_triangulate_as_fans() {
+        let (mesh, _) = parse(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap();
+        let vertices: Vec<[usize; 3]> = mesh.triangles.iter().map(|t| t.vertices).collect();
+        assert_eq!(vertices, [[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.triangles[0].uvs.is_none());
+        assert!(mesh.triangles[0].normals.is_none());
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_end() {
+        let (mesh, _) = parse(&format!("{}f -4/-4/-1 -3/-3/-1 -2/-2/-1\n", SQUARE)).unwrap();
+        let triangle = &mesh.triangles[0];
+        assert_eq!(triangle.vertices, [0, 1, 2]);
+        assert_eq!(triangle.uvs, Some([0, 1, 2]));
+        assert_eq!(triangle.normals, Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn normal_only_and_uv_only_corners() {
+        let (mesh, _) = parse(&format!("{}f 1//1 2//1 3//1\nf 1/1 3/3 4/4\n", SQUARE)).unwrap();
+        assert_eq!(mesh.triangles[0].normals, Some([0, 0, 0]));
+        assert!(mesh.triangles[0].uvs.is_none());
+        assert_eq!(mesh.triangles[1].uvs, Some([0, 2, 3]));
+        assert!(mesh.triangles[1].normals.is_none());
+    }
+
+    #[test]
+    fn unknown_material_falls_back_to_the_default() {
+        let (mesh, materials) = parse(&format!("{}usemtl missing\nf 1 2 3\n", SQUARE)).unwrap();
+        assert_eq!(mesh.triangles[0].material_id, 0);
+        assert_eq!(materials.len(), 1);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        for face in &[
+            "f 1 2 5",
+            "f 0 1 2",
+            "f -5 1 2",
+            "f 1/5 2/1 3/1",
+            "f 1//2 2//1 3//1",
+        ] {
+            match parse(&format!("{}{}\n", SQUARE, face)) {
+                Err(ObjError::Parse { line, .. }) => assert_eq!(line, 11, "{}", face),
+                _ => panic!("accepted \"{}\"", face),
+            }
+        }
+    }
+}
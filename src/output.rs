@@ -0,0 +1,175 @@
+use crate::exr::PixelType;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum OutputError {
+    MissingDirectory(PathBuf),
+    UnknownFormat(PathBuf),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputError::MissingDirectory(dir) => {
+                write!(f, "output directory {} does not exist", dir.display())
+            }
+            OutputError::UnknownFormat(path) => write!(
+                f,
+                "cannot tell the image format of {}; use .png, .exr, .hdr or .pfm",
+                path.display()
+            ),
+            OutputError::Io(path, e) => write!(f, "cannot write {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Error for OutputError {}
+
+/// Image file format, chosen from the output extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Png,
+    Exr(PixelType),
+    /// Radiance RGBE.
+    Hdr,
+    Pfm,
+}
+
+impl Format {
+    /// Format for `path`'s extension; EXR defaults to 32-bit float channels.
+    pub fn from_path(path: &Path) -> Result<Format, OutputError> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "png" => Ok(Format::Png),
+            "exr" => Ok(Format::Exr(PixelType::Float)),
+            "hdr" => Ok(Format::Hdr),
+            "pfm" => Ok(Format::Pfm),
+            _ => Err(OutputError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+}
+
+/// Expands `{frame}` (zero-padded to four digits) and `{timestamp}` (seconds
+/// since the Unix epoch) in an output path template.
+pub fn expand_template(template: &str, frame: usize, timestamp: u64) -> PathBuf {
+    let path = template
+        .replace("{frame}", &format!("{:04}", frame))
+        .replace("{timestamp}", &timestamp.to_string());
+    PathBuf::from(path)
+}
+
+/// Path for the `name` buffer written next to `path`, e.g. `render.depth.png`
+/// for `render.png`.
+pub fn aov_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, name, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, name),
+    };
+    path.with_file_name(file_name)
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Fails early if the directory `path` would be written into is missing.
+pub fn check_directory(path: &Path) -> Result<(), OutputError> {
+    let dir = parent_dir(path);
+    if !dir.is_dir() {
+        return Err(OutputError::MissingDirectory(dir.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Writes `path` through a temporary file in the same directory and renames it
+/// into place, so readers never observe a partially written image.
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<(), OutputError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    check_directory(path)?;
+    let dir = parent_dir(path);
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "path has no file name");
+            OutputError::Io(path.to_path_buf(), e)
+        })?
+        .to_string_lossy();
+    let mut tmp = TempFile {
+        path: dir.join(format!(".{}.{}.tmp", file_name, process::id())),
+        keep: false,
+    };
+
+    let result = File::create(&tmp.path).and_then(|file| {
+        let mut w = BufWriter::new(file);
+        write(&mut w)?;
+        w.flush()?;
+        w.get_ref().sync_all()?;
+        drop(w);
+        fs::rename(&tmp.path, path)
+    });
+    match result {
+        Ok(()) => {
+            tmp.keep = true;
+            Ok(())
+        }
+        Err(e) => Err(OutputError::Io(path.to_path_buf(), e)),
+    }
+}
+
+/// Removes the temporary file on drop unless it was renamed into place, so
+/// errors and panics in the writer do not leave it behind.
+struct TempFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+
+    #[test]
+    fn write_atomic_removes_temp_file_on_panic() {
+        let dir = std::env::temp_dir().join(format!("ray-output-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.png");
+        let result = panic::catch_unwind(|| {
+            let _ = write_atomic(&path, |_| panic!("encoder failed"));
+        });
+        assert!(result.is_err());
+        let leftovers: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(leftovers.is_empty());
+    }
+}
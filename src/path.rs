@@ -0,0 +1,197 @@
+use crate::pbr::Pbr;
+use crate::render::{
+    fresnel, offset_origin, reflect, refract, Differentials, Tracer, MAX_DISTANCE,
+};
+use crate::sampling::{cosine_hemisphere, power_heuristic, Rng};
+use crate::shape::Hit;
+use crate::Vec3;
+use std::f32::consts::PI;
+
+/// Bounces after which Russian roulette may terminate a path.
+const MIN_BOUNCES: usize = 3;
+
+impl<'a> Tracer<'a> {
+    /// Unidirectional path tracer. At every vertex the lights are sampled
+    /// directly. A PBR material then samples its BRDF; a Phong material picks
+    /// a single lobe in proportion to its weight: cosine-weighted diffuse
+    /// (`albedo[0]`), mirror (`albedo[2]`) or dielectric (`albedo[3]`, split
+    /// by Fresnel). Rays that escape pick up the background, which acts as a
+    /// uniform sky light. An environment map is also sampled at diffuse and
+    /// PBR vertices in proportion to its brightness, combined with BSDF
+    /// sampling by multiple importance sampling.
+    ///
+    /// Also returns the part of the radiance that is direct light at the
+    /// first vertex: its emission and the lights, emitters and background
+    /// sampled from it, or the background if the camera ray escapes.
+    pub fn trace_path(
+        &self,
+        orig: Vec3,
+        dir: Vec3,
+        differentials: Option<Differentials>,
+        rng: &mut Rng,
+    ) -> (Vec3, Vec3) {
+        let mut radiance = Vec3::new(0.0, 0.0, 0.0);
+        let mut first_direct = None;
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut orig = orig;
+        let mut dir = dir;
+        // Density of the diffuse or PBR bounce that produced `dir`, or `None`
+        // for camera rays and specular bounces, which environment sampling
+        // cannot produce.
+        let mut bsdf_pdf: Option<f32> = None;
+        for bounce in 0..=self.settings.max_depth {
+            let (object, mut hit) = match self.intersect_object(&orig, &dir, MAX_DISTANCE) {
+                Some(hit) => hit,
+                None => {
+                    let weight = match (&self.scene.environment, bsdf_pdf) {
+                        (Some(environment), Some(pdf)) => {
+                            power_heuristic(pdf, environment.pdf(&dir))
+                        }
+                        _ => 1.0,
+                    };
+                    let background = self.scene.background_radiance(&dir);
+                    let light = throughput.component_mul(&background) * weight;
+                    radiance += light;
+                    // The BSDF-sampled half of the first vertex's background
+                    // light.
+                    if let (1, Some(_), Some(direct)) = (bounce, bsdf_pdf, &mut first_direct) {
+                        *direct += light;
+                    }
+                    break;
+                }
+            };
+            // Texture filtering follows the camera ray differentials to the
+            // first hit only.
+            if let (0, Some(differentials)) = (bounce, differentials) {
+                differentials.transfer(&mut hit);
+            }
+            self.perturb_normal(&mut hit, &dir);
+            let n = hit.normal;
+            let material = self.material(&hit);
+            // After a diffuse bounce, emitters were already sampled directly.
+            if bsdf_pdf.is_none() || !self.is_emitter(object) {
+                radiance += throughput.component_mul(&self.emitted(&material, &hit, &dir));
+            }
+            let direct = self.direct_light(&hit.point, &n, &dir, &material, rng);
+            radiance += throughput.component_mul(&direct);
+
+            if let Some(pbr) = &material.pbr {
+                match self.scatter_pbr(pbr, &hit, &dir, &mut throughput, &mut radiance, rng) {
+                    Some((next, pdf)) => {
+                        dir = next;
+                        bsdf_pdf = pdf;
+                    }
+                    None => break,
+                }
+            } else {
+                let diffuse = material.diffuse_color * material.albedo[0];
+                let diffuse_weight = diffuse.x.max(diffuse.y).max(diffuse.z).max(0.0);
+                let mirror_weight = material.albedo[2].max(0.0);
+                let dielectric_weight = material.albedo[3].max(0.0);
+                let total = diffuse_weight + mirror_weight + dielectric_weight;
+                if total <= 0.0 {
+                    break;
+                }
+                // Diffuse scattering stays on the side the ray arrived from.
+                let facing = if n.dot(&dir) > 0.0 { -n } else { n };
+                let select = diffuse_weight / total;
+                if let (Some(environment), true) = (&self.scene.environment, select > 0.0) {
+                    let (wi, light, pdf) = environment.sample(rng.next_f32(), rng.next_f32());
+                    let cos = wi.dot(&facing);
+                    if pdf > 0.0
+                        && cos > 0.0
+                        && !self.occluded(offset_origin(&hit.point, &n, &wi), wi, MAX_DISTANCE)
+                    {
+                        let weight = power_heuristic(pdf, select * cos / PI);
+                        let f = diffuse.component_mul(&light) * (cos / (PI * pdf) * weight);
+                        radiance += throughput.component_mul(&f);
+                    }
+                }
+
+                let choice = rng.next_f32() * total;
+                if choice < diffuse_weight {
+                    // pdf cos/PI cancels the Lambertian BRDF's cos/PI.
+                    throughput = throughput.component_mul(&diffuse) * (total / diffuse_weight);
+                    dir = cosine_hemisphere(&facing, rng.next_f32(), rng.next_f32());
+                    bsdf_pdf = Some(select * dir.dot(&facing) / PI);
+                } else if choice < diffuse_weight + mirror_weight {
+                    throughput *= material.albedo[2] * total / mirror_weight;
+                    bsdf_pdf = None;
+                    dir = reflect(&dir, &n).normalize();
+                } else {
+                    throughput *= material.albedo[3] * total / dielectric_weight;
+                    bsdf_pdf = None;
+                    let kr = fresnel(&dir, &n, material.refractive_index);
+                    dir = match refract(&dir, &n, material.refractive_index) {
+                        Some(refracted) if rng.next_f32() >= kr => refracted.normalize(),
+                        _ => reflect(&dir, &n).normalize(),
+                    };
+                }
+            }
+            orig = offset_origin(&hit.point, &n, &dir);
+            if bounce == 0 {
+                first_direct = Some(radiance);
+            }
+
+            if bounce >= MIN_BOUNCES {
+                let survive = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+                if rng.next_f32() >= survive {
+                    break;
+                }
+                throughput /= survive;
+            }
+        }
+        (radiance, first_direct.unwrap_or(radiance))
+    }
+
+    /// Picks the next direction at a PBR vertex, first adding environment
+    /// light sampled with MIS against the BRDF. Returns the direction and the
+    /// density it was sampled with, `None` for the glass lobe, or nothing if
+    /// the path ends.
+    fn scatter_pbr(
+        &self,
+        pbr: &Pbr,
+        hit: &Hit,
+        dir: &Vec3,
+        throughput: &mut Vec3,
+        radiance: &mut Vec3,
+        rng: &mut Rng,
+    ) -> Option<(Vec3, Option<f32>)> {
+        let n = hit.normal;
+        let wo = -dir;
+        let facing = if n.dot(&wo) < 0.0 { -n } else { n };
+        let glass = pbr.glass_weight();
+        let select = 1.0 - glass;
+        if let (Some(environment), true) = (&self.scene.environment, select > 0.0) {
+            let (wi, light, pdf) = environment.sample(rng.next_f32(), rng.next_f32());
+            let cos = wi.dot(&facing);
+            if pdf > 0.0
+                && cos > 0.0
+                && !self.occluded(offset_origin(&hit.point, &n, &wi), wi, MAX_DISTANCE)
+            {
+                let weight = power_heuristic(pdf, select * pbr.pdf(&facing, &wo, &wi));
+                let f = pbr.eval(&facing, &wo, &wi).component_mul(&light) * (cos / pdf * weight);
+                *radiance += throughput.component_mul(&f);
+            }
+        }
+
+        if rng.next_f32() < glass {
+            *throughput = throughput.component_mul(&pbr.base_color);
+            let kr = fresnel(dir, &n, pbr.ior);
+            let next = match refract(dir, &n, pbr.ior) {
+                Some(refracted) if rng.next_f32() >= kr => refracted.normalize(),
+                _ => reflect(dir, &n).normalize(),
+            };
+            return Some((next, None));
+        }
+        let u = [rng.next_f32(), rng.next_f32(), rng.next_f32()];
+        let wi = pbr.sample(&facing, &wo, u)?;
+        let pdf = select * pbr.pdf(&facing, &wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let f = pbr.eval(&facing, &wo, &wi);
+        *throughput = throughput.component_mul(&f) * (wi.dot(&facing) / pdf);
+        Some((wi, Some(pdf)))
+    }
+}
@@ -0,0 +1,299 @@
+use crate::sampling::{cosine_hemisphere, orthonormal_basis};
+use crate::scene::Material;
+use crate::Vec3;
+use std::f32::consts::PI;
+
+/// Roughness below which the GGX lobe is clamped, keeping highlights from
+/// point lights finite.
+const MIN_ROUGHNESS: f32 = 0.03;
+
+/// Physically based metallic/roughness material: a GGX microfacet specular
+/// lobe with Smith masking over an energy-conserving Lambertian base, plus an
+/// optional smooth glass lobe for transmission.
+#[derive(Clone, Copy, Debug)]
+pub struct Pbr {
+    /// Diffuse colour of dielectrics, reflectance of metals and tint of glass.
+    pub base_color: Vec3,
+    pub metallic: f32,
+    /// Perceptual roughness; the GGX width is its square.
+    pub roughness: f32,
+    /// Index of refraction of the dielectric part, which sets its specular
+    /// reflectance and the bending of transmitted light.
+    pub ior: f32,
+    /// Fraction of the dielectric part that is smooth glass rather than
+    /// diffuse.
+    pub transmission: f32,
+}
+
+impl Default for Pbr {
+    fn default() -> Self {
+        Pbr {
+            base_color: Vec3::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            ior: 1.5,
+            transmission: 0.0,
+        }
+    }
+}
+
+impl Pbr {
+    /// Approximates a Phong material. The mirror weight `albedo[2]` becomes
+    /// metalness, the dielectric weight `albedo[3]` transmission, and the
+    /// specular exponent a roughness through the Blinn-Phong to Beckmann
+    /// correspondence. Phong mirrors are perfect whatever their exponent, so
+    /// metals get smoother accordingly.
+    pub fn from_phong(material: &Material) -> Pbr {
+        let albedo = material.albedo;
+        let diffuse = material.diffuse_color * albedo[0].max(0.0);
+        let diffuse_weight = diffuse.x.max(diffuse.y).max(diffuse.z).max(0.0);
+        let mirror_weight = albedo[2].max(0.0);
+        let glass_weight = albedo[3].max(0.0);
+        let total = diffuse_weight + mirror_weight + glass_weight;
+        if total <= 0.0 {
+            return Pbr {
+                base_color: Vec3::new(0.0, 0.0, 0.0),
+                ..Pbr::default()
+            };
+        }
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let base_color = (diffuse * diffuse_weight
+            + white * (mirror_weight.min(1.0) * mirror_weight)
+            + white * (glass_weight.min(1.0) * glass_weight))
+            / total;
+        let metallic = mirror_weight / total;
+        let transmission = if diffuse_weight + glass_weight > 0.0 {
+            glass_weight / (diffuse_weight + glass_weight)
+        } else {
+            0.0
+        };
+        let alpha = (2.0 / (material.specular_exponent.max(0.0) + 2.0)).sqrt();
+        Pbr {
+            base_color: base_color.map(|c| c.clamp(0.0, 1.0)),
+            metallic,
+            roughness: alpha.sqrt() * (1.0 - metallic),
+            ior: if material.refractive_index > 1.0 {
+                material.refractive_index
+            } else {
+                1.5
+            },
+            transmission,
+        }
+    }
+
+    /// Index of refraction whose normal-incidence reflectance is `specular`.
+    pub fn ior_from_specular(specular: f32) -> f32 {
+        let r = specular.clamp(0.0, 0.99).sqrt();
+        (1.0 + r) / (1.0 - r)
+    }
+
+    /// Weight of the smooth glass lobe, which integrators trace as a
+    /// reflection/refraction pair since it is not part of `eval`.
+    pub fn glass_weight(&self) -> f32 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    fn alpha(&self) -> f32 {
+        let r = self.roughness.max(MIN_ROUGHNESS);
+        r * r
+    }
+
+    /// Reflectance of the dielectric part at normal incidence.
+    fn dielectric_f0(&self) -> f32 {
+        let r = (self.ior - 1.0) / (self.ior + 1.0);
+        r * r
+    }
+
+    fn f0(&self) -> Vec3 {
+        let dielectric = Vec3::new(1.0, 1.0, 1.0) * self.dielectric_f0();
+        dielectric * (1.0 - self.metallic) + self.base_color * self.metallic
+    }
+
+    /// Reflectance of a mirror-smooth version of the surface viewed at
+    /// `cos_theta`, used by the Whitted integrator to weight reflection rays.
+    pub fn mirror_reflectance(&self, cos_theta: f32) -> Vec3 {
+        let smooth = (1.0 - self.roughness).clamp(0.0, 1.0);
+        schlick(&self.f0(), cos_theta) * ((1.0 - self.glass_weight()) * smooth * smooth)
+    }
+
+    /// BRDF of the diffuse and GGX lobes for unit directions `wo` (towards
+    /// the viewer) and `wi` (towards the light) around the normal `n` facing
+    /// `wo`.
+    pub fn eval(&self, n: &Vec3, wo: &Vec3, wi: &Vec3) -> Vec3 {
+        let n_dot_o = n.dot(wo);
+        let n_dot_i = n.dot(wi);
+        if n_dot_o <= 0.0 || n_dot_i <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let h = (wo + wi).normalize();
+        let o_dot_h = wo.dot(&h).max(0.0);
+        let alpha = self.alpha();
+        let d = ggx(n.dot(&h), alpha);
+        let g = smith_g1(n_dot_o, alpha) * smith_g1(n_dot_i, alpha);
+        let f = schlick(&self.f0(), o_dot_h);
+        let specular = f * (d * g / (4.0 * n_dot_o * n_dot_i) * (1.0 - self.glass_weight()));
+        // The diffuse base only receives light the coating did not reflect.
+        let opaque = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let coat = 1.0 - schlick_scalar(self.dielectric_f0(), o_dot_h);
+        specular + self.base_color * (opaque * coat / PI)
+    }
+
+    /// Probability of sampling the GGX lobe rather than the diffuse one.
+    fn specular_probability(&self, n_dot_o: f32) -> f32 {
+        let f = schlick(&self.f0(), n_dot_o);
+        let specular = luminance(&f) * (1.0 - self.glass_weight());
+        let opaque = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let diffuse = luminance(&self.base_color) * opaque;
+        if specular + diffuse <= 0.0 {
+            1.0
+        } else {
+            specular / (specular + diffuse)
+        }
+    }
+
+    /// Direction `wi` sampled for `eval` from three uniform numbers, or `None`
+    /// if the sample falls below the surface.
+    pub fn sample(&self, n: &Vec3, wo: &Vec3, u: [f32; 3]) -> Option<Vec3> {
+        let n_dot_o = n.dot(wo);
+        if n_dot_o <= 0.0 {
+            return None;
+        }
+        if u[0] >= self.specular_probability(n_dot_o) {
+            return Some(cosine_hemisphere(n, u[1], u[2]));
+        }
+        // Half vector with density D(h) cos(theta_h).
+        let alpha2 = self.alpha() * self.alpha();
+        let cos_theta = ((1.0 - u[1]) / (1.0 + (alpha2 - 1.0) * u[1])).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u[2];
+        let (t, s) = orthonormal_basis(n);
+        let h = t * (sin_theta * phi.cos()) + s * (sin_theta * phi.sin()) + n * cos_theta;
+        let wi = h * (2.0 * wo.dot(&h)) - wo;
+        if wi.dot(n) <= 0.0 {
+            None
+        } else {
+            Some(wi.normalize())
+        }
+    }
+
+    /// Solid-angle density with which `sample` returns `wi`.
+    pub fn pdf(&self, n: &Vec3, wo: &Vec3, wi: &Vec3) -> f32 {
+        let n_dot_o = n.dot(wo);
+        let n_dot_i = n.dot(wi);
+        if n_dot_o <= 0.0 || n_dot_i <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        let o_dot_h = wo.dot(&h);
+        let n_dot_h = n.dot(&h);
+        let specular = if o_dot_h > 0.0 {
+            ggx(n_dot_h, self.alpha()) * n_dot_h / (4.0 * o_dot_h)
+        } else {
+            0.0
+        };
+        let p = self.specular_probability(n_dot_o);
+        p * specular + (1.0 - p) * n_dot_i / PI
+    }
+}
+
+/// GGX (Trowbridge-Reitz) normal distribution.
+fn ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    if n_dot_h <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d * d)
+}
+
+/// Smith masking for GGX in one direction.
+fn smith_g1(n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + (alpha2 + (1.0 - alpha2) * n_dot_v * n_dot_v).sqrt())
+}
+
+fn schlick_scalar(f0: f32, cos_theta: f32) -> f32 {
+    f0 + (1.0 - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick(f0: &Vec3, cos_theta: f32) -> Vec3 {
+    let w = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * w
+}
+
+fn luminance(c: &Vec3) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampling::Rng;
+
+    /// Regions of the hemisphere around +z the sampled directions are
+    /// sorted into: quadrants in azimuth, split at 45 degrees from the pole.
+    fn region(w: &Vec3) -> usize {
+        let quadrant = match (w.x >= 0.0, w.y >= 0.0) {
+            (true, true) => 0,
+            (false, true) => 1,
+            (false, false) => 2,
+            (true, false) => 3,
+        };
+        quadrant + if w.z > 0.5f32.sqrt() { 0 } else { 4 }
+    }
+
+    #[test]
+    fn sample_follows_pdf() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let materials = [(0.5, 0.0), (0.3, 1.0), (1.0, 0.5)];
+        let views = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.75, 0.3, 0.5).normalize(),
+        ];
+        for &(roughness, metallic) in &materials {
+            let pbr = Pbr {
+                roughness,
+                metallic,
+                ..Pbr::default()
+            };
+            for wo in &views {
+                // Probability of each region, integrating the pdf over a grid
+                // in cos(theta) and phi, where the solid angle is uniform.
+                let mut expected = [0f32; 8];
+                let steps = 200;
+                let cell = 2.0 * PI / (steps * steps) as f32;
+                for i in 0..steps {
+                    let z = (i as f32 + 0.5) / steps as f32;
+                    let r = (1.0 - z * z).sqrt();
+                    for j in 0..steps {
+                        let phi = 2.0 * PI * (j as f32 + 0.5) / steps as f32;
+                        let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                        expected[region(&wi)] += pbr.pdf(&n, wo, &wi) * cell;
+                    }
+                }
+                let mut found = [0f32; 8];
+                let count = 40_000;
+                let mut rng = Rng::new(1, 2);
+                for _ in 0..count {
+                    let u = [rng.next_f32(), rng.next_f32(), rng.next_f32()];
+                    if let Some(wi) = pbr.sample(&n, wo, u) {
+                        assert!((wi.norm() - 1.0).abs() < 1e-4 && wi.z > 0.0);
+                        found[region(&wi)] += 1.0 / count as f32;
+                    }
+                }
+                for (k, (e, f)) in expected.iter().zip(&found).enumerate() {
+                    assert!(
+                        (e - f).abs() < 0.015,
+                        "roughness {} metallic {} wo {:?} region {}: pdf {} sampled {}",
+                        roughness,
+                        metallic,
+                        wo,
+                        k,
+                        e,
+                        f
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,776 @@
+use crate::aov::Aov;
+use crate::bvh::{Aabb, Bvh};
+use crate::film::Film;
+use crate::filter::Filter;
+use crate::image::Image;
+use crate::pbr::Pbr;
+use crate::sampling::Rng;
+use crate::scene::{Material, Scene};
+use crate::scheduler;
+use crate::shape::Hit;
+use crate::{Vec2, Vec3};
+use std::f32::consts::PI;
+use std::mem::swap;
+use std::sync::Mutex;
+
+/// Light transport algorithm used to compute each sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    /// Recursive Whitted-style ray tracing: direct light, mirror reflection
+    /// and refraction only.
+    Whitted,
+    /// Monte Carlo path tracing with global illumination.
+    PathTracer,
+}
+
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub threads: usize,
+    pub tile_size: usize,
+    pub max_depth: usize,
+    pub integrator: Integrator,
+    /// Samples per pixel.
+    pub samples: usize,
+    pub filter: Filter,
+    /// Shadow rays per area light or emissive shape at each shading point.
+    pub light_samples: usize,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 1024,
+            height: 768,
+            threads: scheduler::default_threads(),
+            tile_size: 32,
+            max_depth: 4,
+            integrator: Integrator::Whitted,
+            samples: 1,
+            filter: Filter::default(),
+            light_samples: 16,
+        }
+    }
+}
+
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    render_aovs(scene, settings, &[]).0
+}
+
+/// Renders the colour image together with one image per requested AOV.
+pub fn render_aovs(scene: &Scene, settings: &RenderSettings, aovs: &[Aov]) -> (Image, Vec<Image>) {
+    let tracer = Tracer::new(scene, settings);
+    let width = settings.width;
+    let height = settings.height;
+    let filter = settings.filter;
+    // Samples near a tile edge also land in neighbouring pixels.
+    let pad = (filter.radius() - 0.5).ceil().max(0.0) as usize;
+    // The colour film comes first, followed by one film per AOV.
+    let films = Mutex::new(
+        (0..=aovs.len())
+            .map(|_| Film::new(0, 0, width, height))
+            .collect::<Vec<_>>(),
+    );
+    let tiles = scheduler::tiles(width, height, settings.tile_size);
+    scheduler::run(tiles, settings.threads, |tile| {
+        let x0 = tile.x0.saturating_sub(pad);
+        let y0 = tile.y0.saturating_sub(pad);
+        let x1 = (tile.x1 + pad).min(width);
+        let y1 = (tile.y1 + pad).min(height);
+        let mut local: Vec<Film> = (0..=aovs.len())
+            .map(|_| Film::new(x0, y0, x1 - x0, y1 - y0))
+            .collect();
+        let mut values = vec![Vec3::new(0.0, 0.0, 0.0); aovs.len()];
+        for j in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                for sample in 0..settings.samples.max(1) {
+                    let mut rng = Rng::for_pixel(i, j, sample);
+                    let (x, y) = sample_position(i, j, sample, settings.samples, &mut rng);
+                    let color = tracer.sample_aovs(x, y, &mut rng, aovs, &mut values);
+                    local[0].add_sample(x, y, color, &filter);
+                    for (film, value) in local[1..].iter_mut().zip(&values) {
+                        film.add_sample(x, y, *value, &filter);
+                    }
+                }
+            }
+        }
+        let mut films = films.lock().unwrap();
+        for (film, local) in films.iter_mut().zip(&local) {
+            film.merge(local);
+        }
+    });
+    let films = films.into_inner().unwrap();
+    // Only the colour is clamped; AOVs such as normals and indirect light
+    // are signed.
+    let color = films[0].to_image(true);
+    let images = films[1..].iter().map(|film| film.to_image(false)).collect();
+    (color, images)
+}
+
+/// Image position of sample `sample` out of `samples` in pixel `(i, j)`. A
+/// single sample goes through the pixel centre; otherwise the pixel is split
+/// into the largest grid of strata, as square as possible, that `samples`
+/// fills completely, with each sample jittered inside its cell. Samples left
+/// over fall anywhere in the pixel.
+pub fn sample_position(
+    i: usize,
+    j: usize,
+    sample: usize,
+    samples: usize,
+    rng: &mut Rng,
+) -> (f32, f32) {
+    if samples <= 1 {
+        return (i as f32 + 0.5, j as f32 + 0.5);
+    }
+    let ny = ((samples as f32).sqrt().floor() as usize).max(1);
+    let nx = samples / ny;
+    let (sx, sy) = if sample < nx * ny {
+        (
+            ((sample % nx) as f32 + rng.next_f32()) / nx as f32,
+            ((sample / nx) as f32 + rng.next_f32()) / ny as f32,
+        )
+    } else {
+        (rng.next_f32(), rng.next_f32())
+    };
+    (i as f32 + sx, j as f32 + sy)
+}
+
+/// A scene prepared for rendering: the scene itself plus the acceleration
+/// structure built over its shapes.
+pub struct Tracer<'a> {
+    pub scene: &'a Scene,
+    pub settings: &'a RenderSettings,
+    /// Shapes with finite bounds, indexed by BVH leaves.
+    bounded: Vec<usize>,
+    bvh: Bvh,
+    /// Shapes without finite bounds, tested against every ray.
+    unbounded: Vec<usize>,
+    /// Emissive shapes that can be sampled as lights.
+    emitters: Vec<usize>,
+}
+
+/// Rays are considered to escape to the background beyond this distance.
+pub const MAX_DISTANCE: f32 = 1000.0;
+
+impl<'a> Tracer<'a> {
+    pub fn new(scene: &'a Scene, settings: &'a RenderSettings) -> Tracer<'a> {
+        let mut bounded = vec![];
+        let mut bounds: Vec<Aabb> = vec![];
+        let mut unbounded = vec![];
+        for (i, shape) in scene.shapes.iter().enumerate() {
+            match shape.bounds() {
+                Some(b) => {
+                    bounded.push(i);
+                    bounds.push(b);
+                }
+                None => unbounded.push(i),
+            }
+        }
+        let emitters = (0..scene.shapes.len())
+            .filter(|&i| {
+                let shape = &scene.shapes[i];
+                shape.area() > 0.0
+                    && shape.material_ids().iter().any(|&id| {
+                        let material = &scene.materials[id];
+                        material.emission != Vec3::zeros() || material.emission_texture.is_some()
+                    })
+            })
+            .collect();
+        Tracer {
+            scene,
+            settings,
+            bounded,
+            bvh: Bvh::build(&bounds),
+            unbounded,
+            emitters,
+        }
+    }
+
+    /// Radiance along the camera ray through image position `(x, y)`.
+    pub fn sample(&self, x: f32, y: f32, rng: &mut Rng) -> Vec3 {
+        self.sample_aovs(x, y, rng, &[], &mut [])
+    }
+
+    /// Like `sample`, also storing the value of each of `aovs` in `values`.
+    pub fn sample_aovs(
+        &self,
+        x: f32,
+        y: f32,
+        rng: &mut Rng,
+        aovs: &[Aov],
+        values: &mut [Vec3],
+    ) -> Vec3 {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let lens = Vec2::new(rng.next_f32(), rng.next_f32());
+        let camera = &self.scene.camera;
+        let (orig, dir) =
+            match camera.generate_ray(x, y, self.settings.width, self.settings.height, lens) {
+                Some(ray) => ray,
+                None => {
+                    values.iter_mut().for_each(|v| *v = zero);
+                    return zero;
+                }
+            };
+        let differentials = self.camera_differentials(x, y, lens, &orig, &dir);
+        let (color, direct) = match self.settings.integrator {
+            Integrator::Whitted => self.cast_ray(orig, dir, differentials, 0, rng),
+            Integrator::PathTracer => self.trace_path(orig, dir, differentials, rng),
+        };
+        if !aovs.is_empty() {
+            let mut first = self.intersect_object(&orig, &dir, MAX_DISTANCE);
+            if let (Some((_, hit)), Some(differentials)) = (&mut first, differentials) {
+                differentials.transfer(hit);
+            }
+            if let Some((_, hit)) = &mut first {
+                self.perturb_normal(hit, &dir);
+            }
+            for (value, &aov) in values.iter_mut().zip(aovs) {
+                *value = self.aov(aov, first.as_ref(), &color, &direct);
+            }
+        }
+        color
+    }
+
+    /// Rays through the neighbouring image positions, brought closer to the
+    /// primary ray when several samples share a pixel.
+    fn camera_differentials(
+        &self,
+        x: f32,
+        y: f32,
+        lens: Vec2,
+        orig: &Vec3,
+        dir: &Vec3,
+    ) -> Option<Differentials> {
+        let camera = &self.scene.camera;
+        let (width, height) = (self.settings.width, self.settings.height);
+        let (x_orig, x_dir) = camera.generate_ray(x + 1.0, y, width, height, lens)?;
+        let (y_orig, y_dir) = camera.generate_ray(x, y + 1.0, width, height, lens)?;
+        let scale = (1.0 / (self.settings.samples.max(1) as f32).sqrt()).max(0.125);
+        Some(Differentials {
+            x_orig: orig + (x_orig - orig) * scale,
+            x_dir: dir + (x_dir - dir) * scale,
+            y_orig: orig + (y_orig - orig) * scale,
+            y_dir: dir + (y_dir - dir) * scale,
+        })
+    }
+
+    /// Whitted-style radiance along a ray, together with the part of it that
+    /// is emitted by the first hit or reflected there straight from the
+    /// lights (the background itself if nothing is hit). `differentials`,
+    /// when known, set the texture filter width and follow the ray through
+    /// mirror reflection and refraction.
+    pub fn cast_ray(
+        &self,
+        orig: Vec3,
+        dir: Vec3,
+        differentials: Option<Differentials>,
+        depth: usize,
+        rng: &mut Rng,
+    ) -> (Vec3, Vec3) {
+        if depth > self.settings.max_depth {
+            let background = self.scene.background_radiance(&dir);
+            return (background, background);
+        }
+        let mut hit = match self.intersect(&orig, &dir, MAX_DISTANCE) {
+            Some(hit) => hit,
+            None => {
+                let background = self.scene.background_radiance(&dir);
+                return (background, background);
+            }
+        };
+        let surface = differentials.and_then(|d| d.transfer(&mut hit));
+        self.perturb_normal(&mut hit, &dir);
+        let point = hit.point;
+        let n = hit.normal;
+        let material = self.material(&hit);
+        let emission = self.emitted(&material, &hit, &dir);
+        if let Some(pbr) = &material.pbr {
+            let direct = emission + self.direct_light(&point, &n, &dir, &material, rng);
+            let specular = self.pbr_specular(pbr, &hit, &dir, surface, depth, rng);
+            return (direct + specular, direct);
+        }
+
+        let offset = |d: &Vec3| offset_origin(&point, &n, d);
+        // albedo[2] is a plain mirror term; albedo[3] weights the dielectric
+        // lobe, which Fresnel splits between reflection and transmission.
+        let transparent = material.albedo[3] > 0.0;
+        let reflect_dir: Vec3 = reflect(&dir, &n).normalize();
+        let reflect_color = if material.albedo[2] > 0.0 || transparent {
+            let differentials = surface.map(|d| d.reflect(&n));
+            self.cast_ray(
+                offset(&reflect_dir),
+                reflect_dir,
+                differentials,
+                depth + 1,
+                rng,
+            )
+            .0
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        };
+        let mut dielectric_color = Vec3::new(0.0, 0.0, 0.0);
+        if transparent {
+            let kr = fresnel(&dir, &n, material.refractive_index);
+            dielectric_color = reflect_color * kr;
+            if let Some(refract_dir) = refract(&dir, &n, material.refractive_index) {
+                let refract_dir = refract_dir.normalize();
+                let differentials = surface.and_then(|d| d.refract(&n, material.refractive_index));
+                let (refract_color, _) = self.cast_ray(
+                    offset(&refract_dir),
+                    refract_dir,
+                    differentials,
+                    depth + 1,
+                    rng,
+                );
+                dielectric_color += refract_color * (1.0 - kr);
+            }
+        }
+
+        let direct = emission + self.direct_light(&point, &n, &dir, &material, rng);
+        let color =
+            direct + reflect_color * material.albedo[2] + dielectric_color * material.albedo[3];
+        (color, direct)
+    }
+
+    /// Light a PBR surface passes on from other surfaces in the Whitted
+    /// integrator: one mirror reflection weighted by the smooth-surface
+    /// reflectance, and the glass lobe traced as in the Phong dielectric.
+    fn pbr_specular(
+        &self,
+        pbr: &Pbr,
+        hit: &Hit,
+        dir: &Vec3,
+        surface: Option<Differentials>,
+        depth: usize,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        let (point, n) = (&hit.point, &hit.normal);
+        let offset = |d: &Vec3| offset_origin(point, n, d);
+        let mirror = pbr.mirror_reflectance(n.dot(dir).abs());
+        let glass = pbr.glass_weight();
+        if mirror.max() <= 0.0 && glass <= 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let reflect_dir = reflect(dir, n).normalize();
+        let differentials = surface.map(|d| d.reflect(n));
+        let (reflect_color, _) = self.cast_ray(
+            offset(&reflect_dir),
+            reflect_dir,
+            differentials,
+            depth + 1,
+            rng,
+        );
+        let mut color = mirror.component_mul(&reflect_color);
+        if glass > 0.0 {
+            let kr = fresnel(dir, n, pbr.ior);
+            let mut glass_color = reflect_color * kr;
+            if let Some(refract_dir) = refract(dir, n, pbr.ior) {
+                let refract_dir = refract_dir.normalize();
+                let differentials = surface.and_then(|d| d.refract(n, pbr.ior));
+                let (refract_color, _) = self.cast_ray(
+                    offset(&refract_dir),
+                    refract_dir,
+                    differentials,
+                    depth + 1,
+                    rng,
+                );
+                glass_color += refract_color * (1.0 - kr);
+            }
+            color += pbr.base_color.component_mul(&glass_color) * glass;
+        }
+        color
+    }
+
+    /// Light reflected towards `-dir` at `point` straight from the scene
+    /// lights and emissive shapes, with shadows. Light intensities fold in a
+    /// factor of PI, so a white Phong diffuse lobe and a white Lambertian PBR
+    /// base respond alike.
+    pub fn direct_light(
+        &self,
+        point: &Vec3,
+        n: &Vec3,
+        dir: &Vec3,
+        material: &Material,
+        rng: &mut Rng,
+    ) -> Vec3 {
+        if let Some(pbr) = &material.pbr {
+            let wo = -dir;
+            let facing = if n.dot(&wo) < 0.0 { -n } else { *n };
+            let mut reflected = Vec3::new(0.0, 0.0, 0.0);
+            self.visit_lights(point, n, rng, |light_dir, intensity| {
+                let cos = light_dir.dot(&facing);
+                if cos > 0.0 {
+                    let f = pbr.eval(&facing, &wo, light_dir);
+                    reflected += f.component_mul(intensity) * (PI * cos);
+                }
+            });
+            return reflected;
+        }
+        let mut diffuse_light_intensity = Vec3::new(0.0, 0.0, 0.0);
+        let mut specular_light_intensity = Vec3::new(0.0, 0.0, 0.0);
+        self.visit_lights(point, n, rng, |light_dir, intensity| {
+            diffuse_light_intensity += intensity * 0f32.max(light_dir.dot(n));
+            specular_light_intensity += intensity
+                * 0f32
+                    .max(-reflect(&(-light_dir), n).dot(dir))
+                    .powf(material.specular_exponent);
+        });
+        material
+            .diffuse_color
+            .component_mul(&diffuse_light_intensity)
+            * material.albedo[0]
+            + specular_light_intensity * material.albedo[1]
+    }
+
+    /// Calls `visit` with the direction and intensity of every unshadowed
+    /// light sample at `point`: first the scene lights, then the emissive
+    /// shapes. Emitters are sampled uniformly by area with stratified points,
+    /// each acting as a coloured point light of intensity
+    /// `emission * cos * area / (PI * distance^2)`, which agrees with what the
+    /// path tracer collects by hitting the shape.
+    fn visit_lights<F>(&self, point: &Vec3, n: &Vec3, rng: &mut Rng, mut visit: F)
+    where
+        F: FnMut(&Vec3, &Vec3),
+    {
+        for light in &self.scene.lights {
+            light.sample(point, self.settings.light_samples, rng, |sample| {
+                let shadow_orig = offset_origin(point, n, &sample.dir);
+                if !self.occluded(shadow_orig, sample.dir, sample.distance) {
+                    visit(&sample.dir, &sample.intensity);
+                }
+            });
+        }
+        if self.emitters.is_empty() {
+            return;
+        }
+        let strata = (self.settings.light_samples.max(1) as f32).sqrt().ceil() as usize;
+        let count = (strata * strata) as f32;
+        for &index in &self.emitters {
+            let shape = &self.scene.shapes[index];
+            let area = shape.area();
+            for s in 0..strata * strata {
+                let u = Vec2::new(
+                    ((s % strata) as f32 + rng.next_f32()) / strata as f32,
+                    ((s / strata) as f32 + rng.next_f32()) / strata as f32,
+                );
+                let sample = match shape.sample_surface(&u) {
+                    Some(sample) => sample,
+                    None => continue,
+                };
+                let offset = sample.point - point;
+                let distance = offset.norm();
+                if distance <= 1e-2 {
+                    continue;
+                }
+                let light_dir = offset / distance;
+                let cos_light = -light_dir.dot(&sample.normal);
+                if cos_light <= 0.0 {
+                    continue;
+                }
+                // Both ends are pushed off their surfaces, or oblique rays to a
+                // flat emitter would hit it just before the sampled point.
+                let shadow_orig = offset_origin(point, n, &light_dir);
+                let shadow_end = offset_origin(&sample.point, &sample.normal, &-light_dir);
+                let shadow = shadow_end - shadow_orig;
+                let shadow_distance = shadow.norm();
+                if self.occluded(shadow_orig, shadow / shadow_distance, shadow_distance) {
+                    continue;
+                }
+                let emission = self.material(&sample).emission;
+                let intensity = emission * (cos_light * area / (PI * distance * distance * count));
+                visit(&light_dir, &intensity);
+            }
+        }
+    }
+
+    /// True if `index` is an emissive shape that `direct_light` samples.
+    pub fn is_emitter(&self, index: usize) -> bool {
+        self.emitters.contains(&index)
+    }
+
+    /// Nearest hit among all shapes closer than `t_max`.
+    pub fn intersect(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> Option<Hit> {
+        self.intersect_object(orig, dir, t_max).map(|(_, hit)| hit)
+    }
+
+    /// Nearest hit together with the index of the shape in `scene.shapes`.
+    pub fn intersect_object(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> Option<(usize, Hit)> {
+        let shapes = &self.scene.shapes;
+        let bounded = &self.bounded;
+        let mut nearest: Option<(usize, Hit)> = None;
+        self.bvh.intersect(orig, dir, t_max, |i, t_max| {
+            let hit = shapes[bounded[i]].intersect(orig, dir, t_max)?;
+            nearest = Some((bounded[i], hit));
+            Some(hit.t)
+        });
+        for &i in &self.unbounded {
+            let t_max = nearest.map_or(t_max, |(_, hit)| hit.t);
+            if let Some(hit) = shapes[i].intersect(orig, dir, t_max) {
+                nearest = Some((i, hit));
+            }
+        }
+        nearest
+    }
+
+    /// True if anything in the scene lies on the ray closer than `max_dist`.
+    pub fn occluded(&self, orig: Vec3, dir: Vec3, max_dist: f32) -> bool {
+        let shapes = &self.scene.shapes;
+        if self
+            .unbounded
+            .iter()
+            .any(|&i| shapes[i].occluded(&orig, &dir, max_dist))
+        {
+            return true;
+        }
+        let bounded = &self.bounded;
+        self.bvh.occluded(&orig, &dir, max_dist, |i, t_max| {
+            shapes[bounded[i]].occluded(&orig, &dir, t_max)
+        })
+    }
+
+    /// Applies the material's bump and normal maps to the shading normal,
+    /// bump first. Where the result would face away from the viewer the
+    /// normal is left alone.
+    pub fn perturb_normal(&self, hit: &mut Hit, dir: &Vec3) {
+        let material = &self.scene.materials[hit.material_id];
+        if material.bump_texture.is_none() && material.normal_texture.is_none() {
+            return;
+        }
+        let textures = &self.scene.textures;
+        let mut shading = *hit;
+        if let Some(texture) = material.bump_texture {
+            shading.normal = textures[texture].bumped_normal(&shading, material.bump_scale);
+        }
+        if let Some(texture) = material.normal_texture {
+            shading.normal = textures[texture].mapped_normal(&shading);
+        }
+        if shading.normal.dot(dir) * hit.normal.dot(dir) > 0.0 {
+            hit.normal = shading.normal;
+        }
+    }
+
+    /// Light emitted at a hit towards `-dir`. Surfaces only emit on the side
+    /// their normal faces, as when `visit_lights` samples them.
+    pub fn emitted(&self, material: &Material, hit: &Hit, dir: &Vec3) -> Vec3 {
+        if hit.normal.dot(dir) < 0.0 {
+            material.emission
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    /// Material at a hit, with textures evaluated.
+    pub fn material(&self, hit: &Hit) -> Material {
+        let textures = &self.scene.textures;
+        let mut material = self.scene.materials[hit.material_id];
+        if let Some(texture) = material.diffuse_texture {
+            material.diffuse_color = textures[texture].color(hit);
+            if let Some(pbr) = &mut material.pbr {
+                pbr.base_color = material.diffuse_color;
+            }
+        }
+        if let Some(texture) = material.emission_texture {
+            material.emission = textures[texture].color(hit);
+        }
+        if let Some(pbr) = &mut material.pbr {
+            if let Some(texture) = material.roughness_texture {
+                pbr.roughness = textures[texture].value(hit).clamp(0.0, 1.0);
+            }
+            if let Some(texture) = material.metallic_texture {
+                pbr.metallic = textures[texture].value(hit).clamp(0.0, 1.0);
+            }
+            if let Some(texture) = material.transmission_texture {
+                pbr.transmission = textures[texture].value(hit).clamp(0.0, 1.0);
+            }
+        }
+        material
+    }
+}
+
+/// Rays offset from a main ray by one pixel in x and in y. Where they meet
+/// the tangent plane at a hit tells how much of a texture the pixel covers.
+#[derive(Clone, Copy, Debug)]
+pub struct Differentials {
+    pub x_orig: Vec3,
+    pub x_dir: Vec3,
+    pub y_orig: Vec3,
+    pub y_dir: Vec3,
+}
+
+impl Differentials {
+    /// Sets `hit.duvdx` and `hit.duvdy` from where the offset rays cross the
+    /// tangent plane at the hit, and returns the offset rays moved to those
+    /// points. `None` if they miss the plane or the mapping is degenerate.
+    pub fn transfer(&self, hit: &mut Hit) -> Option<Differentials> {
+        let n = hit.normal;
+        let d = n.dot(&hit.point);
+        let tx = (d - n.dot(&self.x_orig)) / n.dot(&self.x_dir);
+        let ty = (d - n.dot(&self.y_orig)) / n.dot(&self.y_dir);
+        if !tx.is_finite() || !ty.is_finite() {
+            return None;
+        }
+        let px = self.x_orig + self.x_dir * tx;
+        let py = self.y_orig + self.y_dir * ty;
+        // Least-squares fit of the position offsets to dpdu and dpdv.
+        let (dpdu, dpdv) = (hit.dpdu, hit.dpdv);
+        let (a, b, c) = (dpdu.dot(&dpdu), dpdu.dot(&dpdv), dpdv.dot(&dpdv));
+        let det = a * c - b * b;
+        if det.abs() < 1e-20 {
+            return None;
+        }
+        let solve = |dp: Vec3| {
+            let (e, f) = (dpdu.dot(&dp), dpdv.dot(&dp));
+            Vec2::new((c * e - b * f) / det, (a * f - b * e) / det)
+        };
+        hit.duvdx = solve(px - hit.point);
+        hit.duvdy = solve(py - hit.point);
+        Some(Differentials {
+            x_orig: px,
+            y_orig: py,
+            ..*self
+        })
+    }
+
+    /// Offset rays mirrored about `n`, treating the surface as locally flat.
+    pub fn reflect(&self, n: &Vec3) -> Differentials {
+        Differentials {
+            x_dir: reflect(&self.x_dir, n).normalize(),
+            y_dir: reflect(&self.y_dir, n).normalize(),
+            ..*self
+        }
+    }
+
+    /// Offset rays refracted through a locally flat surface.
+    pub fn refract(&self, n: &Vec3, refractive_index: f32) -> Option<Differentials> {
+        Some(Differentials {
+            x_dir: refract(&self.x_dir, n, refractive_index)?.normalize(),
+            y_dir: refract(&self.y_dir, n, refractive_index)?.normalize(),
+            ..*self
+        })
+    }
+}
+
+/// Origin for a secondary ray leaving `point` in direction `dir`, nudged off the
+/// surface to the side the ray travels so it does not hit the surface again.
+pub fn offset_origin(point: &Vec3, n: &Vec3, dir: &Vec3) -> Vec3 {
+    if dir.dot(n) < 0.0 {
+        point - n * 1e-2
+    } else {
+        point + n * 1e-2
+    }
+}
+
+pub fn reflect(i: &Vec3, n: &Vec3) -> Vec3 {
+    i - n * 2.0 * i.dot(n)
+}
+
+/// Refracted direction of `i` through a surface with normal `n`, or `None`
+/// on total internal reflection. Works from either side of the surface.
+pub fn refract(i: &Vec3, n: &Vec3, refractive_index: f32) -> Option<Vec3> {
+    let mut cosi = -i.dot(n).clamp(-1.0, 1.0);
+    let mut etai = 1f32;
+    let mut etat = refractive_index;
+    let mut nn = *n;
+    if cosi < 0f32 {
+        cosi = -cosi;
+        swap(&mut etai, &mut etat);
+        nn = -n;
+    }
+    let eta = etai / etat;
+    let k = 1f32 - eta * eta * (1f32 - cosi * cosi);
+    if k < 0f32 {
+        None
+    } else {
+        Some(i * eta + nn * (eta * cosi - k.sqrt()))
+    }
+}
+
+/// Fraction of light reflected at a dielectric boundary, from the exact
+/// Fresnel equations for unpolarised light. Returns 1 on total internal
+/// reflection.
+pub fn fresnel(i: &Vec3, n: &Vec3, refractive_index: f32) -> f32 {
+    let mut cosi = i.dot(n).clamp(-1.0, 1.0);
+    let mut etai = 1f32;
+    let mut etat = refractive_index;
+    if cosi > 0f32 {
+        swap(&mut etai, &mut etat);
+    }
+    let sint = etai / etat * (1f32 - cosi * cosi).max(0.0).sqrt();
+    if sint >= 1f32 {
+        return 1.0;
+    }
+    let cost = (1f32 - sint * sint).max(0.0).sqrt();
+    cosi = cosi.abs();
+    let rs = (etat * cosi - etai * cost) / (etat * cosi + etai * cost);
+    let rp = (etai * cosi - etat * cost) / (etai * cosi + etat * cost);
+    (rs * rs + rp * rp) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_positions_fill_the_strata() {
+        for &(samples, nx, ny) in &[
+            (2, 2, 1),
+            (4, 2, 2),
+            (5, 2, 2),
+            (8, 4, 2),
+            (9, 3, 3),
+            (12, 4, 3),
+        ] {
+            for pixel in 0..20 {
+                let mut cells = vec![0; nx * ny];
+                for sample in 0..samples {
+                    let mut rng = Rng::for_pixel(pixel, 7, sample);
+                    let (x, y) = sample_position(3, 5, sample, samples, &mut rng);
+                    let (sx, sy) = (x - 3.0, y - 5.0);
+                    assert!((0.0..1.0).contains(&sx) && (0.0..1.0).contains(&sy));
+                    if sample < nx * ny {
+                        let cell = (sy * ny as f32) as usize * nx + (sx * nx as f32) as usize;
+                        cells[cell] += 1;
+                    }
+                }
+                assert!(cells.iter().all(|&c| c == 1), "{} samples", samples);
+            }
+        }
+        let mut rng = Rng::new(0, 0);
+        assert_eq!(sample_position(3, 5, 0, 1, &mut rng), (3.5, 5.5));
+    }
+
+    #[test]
+    fn refract_fails_under_total_internal_reflection() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        // Leaving glass of index 1.5 the critical angle is about 41.8 degrees.
+        let steep = Vec3::new(0.5, 0.8, 0.0).normalize();
+        let grazing = Vec3::new(0.8, 0.5, 0.0).normalize();
+        assert!(refract(&steep, &n, 1.5).is_some());
+        assert!(refract(&grazing, &n, 1.5).is_none());
+        assert_eq!(fresnel(&grazing, &n, 1.5), 1.0);
+        // Entering the glass there is always a refracted ray.
+        assert!(refract(&-grazing, &n, 1.5).is_some());
+    }
+
+    #[test]
+    fn refract_obeys_snells_law() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        let i = Vec3::new(0.6, -0.8, 0.0);
+        let t = refract(&i, &n, 1.5).unwrap().normalize();
+        assert!((i.x - 1.5 * t.x).abs() < 1e-5);
+        assert!(t.y < 0.0);
+    }
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+        let n = Vec3::new(0.0, 1.0, 0.0);
+        for &ior in &[1.33f32, 1.5, 2.4] {
+            let expected = ((ior - 1.0) / (ior + 1.0)).powi(2);
+            let entering = fresnel(&-n, &n, ior);
+            let leaving = fresnel(&n, &n, ior);
+            assert!((entering - expected).abs() < 1e-6, "{}", ior);
+            assert!((leaving - expected).abs() < 1e-6, "{}", ior);
+        }
+    }
+}
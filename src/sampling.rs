@@ -0,0 +1,192 @@
+use crate::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Small, fast PCG32 generator. Each pixel sample seeds its own stream, so
+/// renders are reproducible regardless of how tiles are scheduled.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+    inc: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Rng {
+        let mut rng = Rng {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// Generator for sample `sample` of pixel `(x, y)`.
+    pub fn for_pixel(x: usize, y: usize, sample: usize) -> Rng {
+        let pixel = ((y as u64) << 32) | x as u64;
+        Rng::new(pixel.wrapping_mul(0x9E37_79B9_7F4A_7C15), sample as u64)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniform float in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+}
+
+/// Two unit vectors completing `n` to a right-handed orthonormal basis.
+pub fn orthonormal_basis(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    let t = Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let s = Vec3::new(b, sign + n.y * n.y * a, -n.y);
+    (t, s)
+}
+
+/// Cosine-weighted direction on the hemisphere around `n`; the pdf is
+/// `cos(theta) / PI`.
+pub fn cosine_hemisphere(n: &Vec3, u1: f32, u2: f32) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let (t, s) = orthonormal_basis(n);
+    let z = (1.0 - u1).max(0.0).sqrt();
+    (t * (r * phi.cos()) + s * (r * phi.sin()) + n * z).normalize()
+}
+
+/// Uniform point on the unit disk.
+pub fn uniform_disk(u1: f32, u2: f32) -> Vec2 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec2::new(r * phi.cos(), r * phi.sin())
+}
+
+/// Uniform point inside the regular polygon with `sides` vertices on the unit
+/// circle, the first at angle `rotation`.
+pub fn uniform_polygon(sides: u32, rotation: f32, u1: f32, u2: f32) -> Vec2 {
+    // Pick one of the triangles fanning out from the centre, then reuse the
+    // remainder of `u1` to place the point inside it.
+    let scaled = u1 * sides as f32;
+    let side = (scaled as u32).min(sides - 1);
+    let u1 = scaled - side as f32;
+    let step = 2.0 * PI / sides as f32;
+    let a0 = rotation + side as f32 * step;
+    let a1 = a0 + step;
+    let v0 = Vec2::new(a0.cos(), a0.sin());
+    let v1 = Vec2::new(a1.cos(), a1.sin());
+    let s = u1.sqrt();
+    v0 * (s * (1.0 - u2)) + v1 * (s * u2)
+}
+
+/// Piecewise-constant distribution over `[0, 1)` with one bin per entry of
+/// `func`. An all-zero function samples uniformly.
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    /// Average of `func`.
+    pub integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f32>) -> Distribution1D {
+        let n = func.len() as f32;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f.max(0.0) / n);
+        }
+        let integral = cdf[func.len()];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f32 / n
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Sample position in `[0, 1)`, its density and the bin it falls in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        let bin = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[bin + 1] - self.cdf[bin];
+        let offset = if width > 0.0 {
+            (u - self.cdf[bin]) / width
+        } else {
+            0.0
+        };
+        let x = ((bin as f32 + offset) / n as f32).min(1.0 - f32::EPSILON);
+        (x, self.density(bin), bin)
+    }
+
+    /// Density at `x` in `[0, 1)`.
+    pub fn pdf(&self, x: f32) -> f32 {
+        let n = self.func.len();
+        self.density(((x * n as f32) as usize).min(n - 1))
+    }
+
+    fn density(&self, bin: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[bin].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Piecewise-constant distribution over `[0, 1)^2` given row by row: a
+/// marginal over rows, then the row's own distribution over columns.
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    /// Sample point and its density.
+    pub fn sample(&self, u1: f32, u2: f32) -> (Vec2, f32) {
+        let (v, pdf_v, row) = self.marginal.sample(u2);
+        let (u, pdf_u, _) = self.rows[row].sample(u1);
+        (Vec2::new(u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, p: &Vec2) -> f32 {
+        let n = self.rows.len();
+        let row = ((p.y * n as f32) as usize).min(n - 1);
+        self.marginal.pdf(p.y) * self.rows[row].pdf(p.x)
+    }
+}
+
+/// Multiple importance sampling weight for a sample drawn with density `f`
+/// that another strategy could have drawn with density `g`.
+pub fn power_heuristic(f: f32, g: f32) -> f32 {
+    let (f2, g2) = (f * f, g * g);
+    if f2 + g2 > 0.0 {
+        f2 / (f2 + g2)
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,114 @@
+use crate::camera::Camera;
+use crate::environment::Environment;
+pub use crate::light::Light;
+use crate::pbr::Pbr;
+use crate::shape::Shape;
+use crate::texture::Texture;
+use crate::{Vec3, Vec4};
+
+pub type Lights = Vec<Light>;
+
+#[derive(Clone, Copy)]
+pub struct Material {
+    pub refractive_index: f32,
+    pub diffuse_color: Vec3,
+    /// Index into `Scene::textures`; replaces `diffuse_color` when set.
+    pub diffuse_texture: Option<usize>,
+    pub albedo: Vec4,
+    pub specular_exponent: f32,
+    /// Radiance emitted on the side the surface normal faces; emissive
+    /// spheres, rectangles and meshes also act as lights.
+    pub emission: Vec3,
+    /// Replaces the PBR roughness when set.
+    pub roughness_texture: Option<usize>,
+    /// Replaces the PBR metalness when set.
+    pub metallic_texture: Option<usize>,
+    /// Replaces the PBR transmission when set.
+    pub transmission_texture: Option<usize>,
+    /// Replaces `emission` when set.
+    pub emission_texture: Option<usize>,
+    /// Tangent-space normal map perturbing the shading normal.
+    pub normal_texture: Option<usize>,
+    /// Height map perturbing the shading normal, its values scaled by
+    /// `bump_scale` into world units.
+    pub bump_texture: Option<usize>,
+    pub bump_scale: f32,
+    /// Physically based shading, used instead of the Phong fields when set.
+    pub pbr: Option<Pbr>,
+}
+
+/// Everything needed to render a frame: geometry, lights and the camera.
+pub struct Scene {
+    pub camera: Camera,
+    pub shapes: Vec<Box<dyn Shape>>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    pub lights: Lights,
+    /// Colour of rays that escape the scene when there is no environment map.
+    pub background: Vec3,
+    pub environment: Option<Environment>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            refractive_index: 0.0,
+            albedo: Vec4::new(0.0, 0.0, 0.0, 0.0),
+            diffuse_color: Vec3::new(0.0, 0.0, 0.0),
+            diffuse_texture: None,
+            specular_exponent: 0.0,
+            emission: Vec3::new(0.0, 0.0, 0.0),
+            roughness_texture: None,
+            metallic_texture: None,
+            transmission_texture: None,
+            emission_texture: None,
+            normal_texture: None,
+            bump_texture: None,
+            bump_scale: 1.0,
+            pbr: None,
+        }
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            camera: Camera::default(),
+            shapes: vec![],
+            materials: vec![],
+            textures: vec![],
+            lights: vec![],
+            background: Vec3::new(0.2, 0.7, 0.8),
+            environment: None,
+        }
+    }
+}
+
+impl Scene {
+    pub fn add<S: Shape + 'static>(&mut self, shape: S) {
+        self.shapes.push(Box::new(shape));
+    }
+
+    /// Radiance carried by a ray escaping the scene in direction `dir`.
+    pub fn background_radiance(&self, dir: &Vec3) -> Vec3 {
+        match &self.environment {
+            Some(environment) => environment.radiance(dir),
+            None => self.background,
+        }
+    }
+
+    /// Switches every Phong material to its approximate PBR equivalent.
+    pub fn convert_to_pbr(&mut self) {
+        for material in &mut self.materials {
+            if material.pbr.is_none() {
+                material.pbr = Some(Pbr::from_phong(material));
+            }
+        }
+    }
+
+    /// Registers a material and returns the id shapes should refer to it by.
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+
+/// Rectangle of pixels `[x0, x1) x [y0, y1)` rendered as one unit of work.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+/// Splits a `width` x `height` image into `size` x `size` tiles, clipped at the
+/// right and bottom edges so every pixel belongs to exactly one tile.
+pub fn tiles(width: usize, height: usize, size: usize) -> Vec<Tile> {
+    let size = size.max(1);
+    let mut tiles = vec![];
+    for y0 in (0..height).step_by(size) {
+        for x0 in (0..width).step_by(size) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                x1: (x0 + size).min(width),
+                y1: (y0 + size).min(height),
+            });
+        }
+    }
+    tiles
+}
+
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Runs `work` on every tile using `threads` workers. Each worker drains its
+/// own queue from the front and, once empty, steals from the back of the
+/// others, so uneven tiles do not leave threads idle.
+pub fn run<F>(tiles: Vec<Tile>, threads: usize, work: F)
+where
+    F: Fn(Tile) + Sync,
+{
+    let threads = threads.max(1);
+    let queues: Vec<Mutex<VecDeque<Tile>>> =
+        (0..threads).map(|_| Mutex::new(VecDeque::new())).collect();
+    for (index, tile) in tiles.into_iter().enumerate() {
+        queues[index % threads].lock().unwrap().push_back(tile);
+    }
+
+    thread::scope(|s| {
+        for k in 0..threads {
+            let queues = &queues;
+            let work = &work;
+            s.spawn(move || {
+                while let Some(tile) = next_tile(queues, k) {
+                    work(tile);
+                }
+            });
+        }
+    });
+}
+
+fn next_tile(queues: &[Mutex<VecDeque<Tile>>], own: usize) -> Option<Tile> {
+    if let Some(tile) = queues[own].lock().unwrap().pop_front() {
+        return Some(tile);
+    }
+    for offset in 1..queues.len() {
+        let victim = (own + offset) % queues.len();
+        if let Some(tile) = queues[victim].lock().unwrap().pop_back() {
+            return Some(tile);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        for &(width, height, size) in &[(100, 37, 32), (31, 33, 32), (64, 64, 32), (7, 9, 100)] {
+            let mut counts = vec![0; width * height];
+            for tile in tiles(width, height, size) {
+                assert!(tile.x0 < tile.x1 && tile.x1 <= width);
+                assert!(tile.y0 < tile.y1 && tile.y1 <= height);
+                for y in tile.y0..tile.y1 {
+                    for x in tile.x0..tile.x1 {
+                        counts[y * width + x] += 1;
+                    }
+                }
+            }
+            assert!(
+                counts.iter().all(|&c| c == 1),
+                "{}x{} in tiles of {}",
+                width,
+                height,
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn run_works_on_every_tile_once() {
+        let all = tiles(100, 37, 8);
+        let done = Mutex::new(vec![]);
+        run(all.clone(), 3, |tile| done.lock().unwrap().push(tile));
+        let mut done = done.into_inner().unwrap();
+        done.sort_by_key(|t| (t.y0, t.x0));
+        assert_eq!(done, all);
+    }
+}
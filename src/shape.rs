@@ -0,0 +1,347 @@
+use crate::bvh::Aabb;
+use crate::sampling::orthonormal_basis;
+use crate::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Ray/surface intersection record.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    /// Distance along the ray.
+    pub t: f32,
+    pub point: Vec3,
+    /// Position in the shape's own coordinates, so solid textures evaluated
+    /// there move with the object.
+    pub local: Vec3,
+    /// Unit shading normal.
+    pub normal: Vec3,
+    /// Surface texture coordinates.
+    pub uv: Vec2,
+    /// Partial derivatives of the position with respect to `uv`.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    /// Unit tangent along increasing u for normal maps, or zero where there
+    /// is none. Unlike `dpdu` it varies smoothly over meshes with vertex
+    /// normals.
+    pub tangent: Vec3,
+    /// Change of `uv` to the neighbouring pixels in x and y, from ray
+    /// differentials; zero when the tracer has none.
+    pub duvdx: Vec2,
+    pub duvdy: Vec2,
+    /// Index into `Scene::materials`.
+    pub material_id: usize,
+}
+
+impl Hit {
+    /// Tangent and bitangent completing an orthonormal frame with the shading
+    /// normal, the bitangent on the side of increasing v.
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        let n = self.normal;
+        let t = self.tangent - n * n.dot(&self.tangent);
+        if t.norm() < 1e-6 {
+            return orthonormal_basis(&n);
+        }
+        let t = t.normalize();
+        let b = n.cross(&t);
+        if b.dot(&self.dpdv) < 0.0 {
+            (t, -b)
+        } else {
+            (t, b)
+        }
+    }
+}
+
+/// Geometry that can be placed in a scene. Implement this to add new kinds of
+/// primitives; the renderer only ever talks to shapes through it.
+pub trait Shape: Send + Sync {
+    /// Nearest intersection with distance in `(0, t_max)`.
+    fn intersect(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> Option<Hit>;
+
+    /// World-space bounds, or `None` for shapes of infinite extent, which are
+    /// then tested against every ray instead of going into the BVH.
+    fn bounds(&self) -> Option<Aabb>;
+
+    /// Whether anything blocks the ray before `t_max`. Override when this can
+    /// be answered more cheaply than a full `intersect`.
+    fn occluded(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> bool {
+        self.intersect(orig, dir, t_max).is_some()
+    }
+
+    /// Point distributed uniformly over the surface for `u` in `[0, 1)^2`, as a
+    /// hit with `t` unused. Shapes that implement this and `area` can light the
+    /// scene when given an emissive material.
+    fn sample_surface(&self, _u: &Vec2) -> Option<Hit> {
+        None
+    }
+
+    fn area(&self) -> f32 {
+        0.0
+    }
+
+    /// Materials used by the surface, which make it a light if any of them
+    /// is emissive.
+    fn material_ids(&self) -> Vec<usize> {
+        self.sample_surface(&Vec2::new(0.5, 0.5))
+            .map(|hit| vec![hit.material_id])
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+    pub material_id: usize,
+}
+
+impl Sphere {
+    /// Distance to the first intersection in front of `orig`.
+    pub fn ray_intersect(&self, orig: &Vec3, dir: &Vec3) -> Option<f32> {
+        let l: Vec3 = self.center - orig;
+        let tca: f32 = l.dot(dir);
+        let d2 = l.dot(&l) - tca * tca;
+        let r2 = self.radius * self.radius;
+        if d2 > r2 {
+            return None;
+        }
+        let thc = (r2 - d2).sqrt();
+        let t0 = tca - thc;
+        let t1 = tca + thc;
+        if t0 >= 0.0 {
+            Some(t0)
+        } else if t1 >= 0.0 {
+            Some(t1)
+        } else {
+            None
+        }
+    }
+
+    /// Texture coordinates and their position derivatives at the point with
+    /// outward unit `normal`: longitude along u, latitude along v.
+    fn parameterize(&self, normal: &Vec3) -> (Vec2, Vec3, Vec3) {
+        let uv = Vec2::new(
+            0.5 + normal.z.atan2(normal.x) / (2.0 * PI),
+            0.5 + normal.y.clamp(-1.0, 1.0).asin() / PI,
+        );
+        let r = self.radius;
+        let ring = (normal.x * normal.x + normal.z * normal.z).sqrt().max(1e-6);
+        let dpdu = Vec3::new(-normal.z, 0.0, normal.x) * (2.0 * PI * r);
+        let dpdv = Vec3::new(
+            -normal.y * normal.x / ring,
+            ring,
+            -normal.y * normal.z / ring,
+        ) * (PI * r);
+        (uv, dpdu, dpdv)
+    }
+}
+
+impl Shape for Sphere {
+    fn intersect(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> Option<Hit> {
+        let t = self.ray_intersect(orig, dir)?;
+        if t >= t_max {
+            return None;
+        }
+        let point = orig + dir * t;
+        let normal = (point - self.center).normalize();
+        let (uv, dpdu, dpdv) = self.parameterize(&normal);
+        Some(Hit {
+            t,
+            point,
+            local: point - self.center,
+            normal,
+            uv,
+            dpdu,
+            dpdv,
+            tangent: unit_or_zero(&dpdu),
+            duvdx: Vec2::zeros(),
+            duvdy: Vec2::zeros(),
+            material_id: self.material_id,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb {
+            min: self.center - r,
+            max: self.center + r,
+        })
+    }
+
+    fn occluded(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> bool {
+        self.ray_intersect(orig, dir).is_some_and(|t| t < t_max)
+    }
+
+    fn sample_surface(&self, u: &Vec2) -> Option<Hit> {
+        let z = 1.0 - 2.0 * u.x;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y;
+        let normal = Vec3::new(r * phi.cos(), z, r * phi.sin());
+        let (uv, dpdu, dpdv) = self.parameterize(&normal);
+        Some(Hit {
+            t: 0.0,
+            point: self.center + normal * self.radius,
+            local: normal * self.radius,
+            normal,
+            uv,
+            dpdu,
+            dpdv,
+            tangent: unit_or_zero(&dpdu),
+            duvdx: Vec2::zeros(),
+            duvdy: Vec2::zeros(),
+            material_id: self.material_id,
+        })
+    }
+
+    fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+}
+
+/// Flat surface through `center` facing `normal`. With an `extent` it is a
+/// rectangle spanning `±extent.x` along `tangent` and `±extent.y` along
+/// `normal × tangent`; without one it is infinite.
+#[derive(Clone, Copy)]
+pub struct Plane {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub tangent: Vec3,
+    pub extent: Option<Vec2>,
+    pub material_id: usize,
+}
+
+impl Plane {
+    /// Builds a plane, making `normal` unit length and `tangent` an orthonormal
+    /// direction within the plane (any `tangent` parallel to `normal` is replaced).
+    pub fn new(
+        center: Vec3,
+        normal: Vec3,
+        tangent: Vec3,
+        extent: Option<Vec2>,
+        material_id: usize,
+    ) -> Plane {
+        let normal = normal.normalize();
+        let mut tangent = tangent - normal * tangent.dot(&normal);
+        if tangent.norm() < 1e-6 {
+            let axis = if normal.x.abs() < 0.9 {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            tangent = axis - normal * axis.dot(&normal);
+        }
+        Plane {
+            center,
+            normal,
+            tangent: tangent.normalize(),
+            extent,
+            material_id,
+        }
+    }
+
+    pub fn bitangent(&self) -> Vec3 {
+        self.normal.cross(&self.tangent)
+    }
+
+    /// Position derivatives matching the texture coordinates of `intersect`:
+    /// world units for infinite planes, the whole rectangle across `[0, 1]`
+    /// for bounded ones.
+    fn uv_derivatives(&self) -> (Vec3, Vec3) {
+        match self.extent {
+            Some(extent) => (
+                self.tangent * (2.0 * extent.x),
+                self.bitangent() * (2.0 * extent.y),
+            ),
+            None => (self.tangent, self.bitangent()),
+        }
+    }
+}
+
+impl Shape for Plane {
+    fn intersect(&self, orig: &Vec3, dir: &Vec3, t_max: f32) -> Option<Hit> {
+        let denom = dir.dot(&self.normal);
+        if denom.abs() <= 1e-6 {
+            return None;
+        }
+        let t = (self.center - orig).dot(&self.normal) / denom;
+        if t <= 0.0 || t >= t_max {
+            return None;
+        }
+        let point = orig + dir * t;
+        let local = point - self.center;
+        let u = local.dot(&self.tangent);
+        let v = local.dot(&self.bitangent());
+        let uv = match self.extent {
+            Some(extent) => {
+                if u.abs() > extent.x || v.abs() > extent.y {
+                    return None;
+                }
+                Vec2::new(0.5 + 0.5 * u / extent.x, 0.5 + 0.5 * v / extent.y)
+            }
+            None => Vec2::new(u, v),
+        };
+        let (dpdu, dpdv) = self.uv_derivatives();
+        Some(Hit {
+            t,
+            point,
+            local,
+            normal: self.normal,
+            uv,
+            dpdu,
+            dpdv,
+            tangent: self.tangent,
+            duvdx: Vec2::zeros(),
+            duvdy: Vec2::zeros(),
+            material_id: self.material_id,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let extent = self.extent?;
+        let u = self.tangent * extent.x;
+        let v = self.bitangent() * extent.y;
+        let eps = Vec3::new(1e-4, 1e-4, 1e-4);
+        let bounds = Aabb::empty()
+            .grow(&(self.center - u - v))
+            .grow(&(self.center - u + v))
+            .grow(&(self.center + u - v))
+            .grow(&(self.center + u + v));
+        Some(Aabb {
+            min: bounds.min - eps,
+            max: bounds.max + eps,
+        })
+    }
+
+    fn sample_surface(&self, u: &Vec2) -> Option<Hit> {
+        let extent = self.extent?;
+        let point = self.center
+            + self.tangent * (extent.x * (2.0 * u.x - 1.0))
+            + self.bitangent() * (extent.y * (2.0 * u.y - 1.0));
+        let (dpdu, dpdv) = self.uv_derivatives();
+        Some(Hit {
+            t: 0.0,
+            point,
+            local: point - self.center,
+            normal: self.normal,
+            uv: *u,
+            dpdu,
+            dpdv,
+            tangent: self.tangent,
+            duvdx: Vec2::zeros(),
+            duvdy: Vec2::zeros(),
+            material_id: self.material_id,
+        })
+    }
+
+    fn area(&self) -> f32 {
+        self.extent.map_or(0.0, |extent| 4.0 * extent.x * extent.y)
+    }
+}
+
+/// `v` normalized, or zero if it has no length.
+pub fn unit_or_zero(v: &Vec3) -> Vec3 {
+    let norm = v.norm();
+    if norm > 0.0 {
+        v / norm
+    } else {
+        Vec3::zeros()
+    }
+}
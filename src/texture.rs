@@ -0,0 +1,386 @@
+use crate::image::Image;
+use crate::noise;
+use crate::shape::Hit;
+use crate::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Colour that varies over a surface. Textures live in `Scene::textures` and
+/// materials refer to them by index.
+#[derive(Clone)]
+pub enum Texture {
+    /// Solid pattern computed from the hit position.
+    Procedural(Procedural),
+    /// Image mapped by the surface's texture coordinates.
+    Image(ImageTexture),
+}
+
+impl Texture {
+    pub fn color(&self, hit: &Hit) -> Vec3 {
+        match self {
+            Texture::Procedural(texture) => texture.color(hit),
+            Texture::Image(texture) => texture.lookup(&hit.uv, &hit.duvdx, &hit.duvdy),
+        }
+    }
+
+    /// Scalar value for slots such as roughness: the mean of the channels.
+    pub fn value(&self, hit: &Hit) -> f32 {
+        let c = self.color(hit);
+        (c.x + c.y + c.z) / 3.0
+    }
+
+    /// Shading normal read from a tangent-space normal map, whose red, green
+    /// and blue channels hold the components along the tangent, bitangent and
+    /// normal remapped from `[-1, 1]` to `[0, 1]`.
+    pub fn mapped_normal(&self, hit: &Hit) -> Vec3 {
+        let c = self.color(hit) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+        let (t, b) = hit.tangent_frame();
+        let n = t * c.x + b * c.y + hit.normal * c.z;
+        if n.norm() > 0.0 {
+            n.normalize()
+        } else {
+            hit.normal
+        }
+    }
+
+    /// Shading normal of the surface displaced along its normal by `scale`
+    /// times this texture's value. The slope comes from finite differences
+    /// one pixel footprint apart, or a small fixed step without ray
+    /// differentials.
+    pub fn bumped_normal(&self, hit: &Hit, scale: f32) -> Vec3 {
+        let step = |a: f32, b: f32| {
+            let d = 0.5 * (a.abs() + b.abs());
+            if d > 0.0 {
+                d
+            } else {
+                5e-4
+            }
+        };
+        let du = step(hit.duvdx.x, hit.duvdy.x);
+        let dv = step(hit.duvdx.y, hit.duvdy.y);
+        let height = self.value(hit);
+        let slope = |duv: Vec2, dp: Vec3, d: f32| {
+            let mut shifted = *hit;
+            shifted.uv += duv;
+            shifted.point += dp;
+            shifted.local += dp;
+            (self.value(&shifted) - height) * scale / d
+        };
+        let n = hit.normal;
+        let dpdu = hit.dpdu - n * n.dot(&hit.dpdu);
+        let dpdv = hit.dpdv - n * n.dot(&hit.dpdv);
+        let dhdu = slope(Vec2::new(du, 0.0), dpdu * du, du);
+        let dhdv = slope(Vec2::new(0.0, dv), dpdv * dv, dv);
+        let bumped = (dpdu + n * dhdu).cross(&(dpdv + n * dhdv));
+        if bumped.norm() == 0.0 {
+            return n;
+        }
+        let bumped = bumped.normalize();
+        if bumped.dot(&n) < 0.0 {
+            -bumped
+        } else {
+            bumped
+        }
+    }
+}
+
+/// Coordinates a procedural texture is evaluated in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureSpace {
+    World,
+    /// Relative to the shape, so the pattern moves with it.
+    Object,
+}
+
+/// Scalar pattern in `[0, 1]` over 3D space, with features about one unit
+/// across.
+#[derive(Clone, Copy, Debug)]
+pub enum Pattern {
+    /// Cubes alternating between 0 and 1.
+    Checker,
+    /// Slabs alternating between 0 and 1 along `axis`.
+    Stripes { axis: Vec3 },
+    /// Single octave of gradient noise.
+    Noise,
+    Fbm {
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    },
+    Turbulence {
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    },
+    /// Veins along x, distorted by `octaves` of turbulence scaled by
+    /// `strength`.
+    Marble { octaves: u32, strength: f32 },
+    /// Rings around the y axis, `rings` per unit, wobbled by noise scaled by
+    /// `strength`.
+    Wood { rings: f32, strength: f32 },
+    /// Distance to the nearest cell point; `jitter` of 0 gives a regular grid.
+    Voronoi { jitter: f32 },
+}
+
+impl Pattern {
+    pub fn eval(&self, p: &Vec3) -> f32 {
+        match *self {
+            Pattern::Checker => {
+                let parity = p.x.floor() as i64 + p.y.floor() as i64 + p.z.floor() as i64;
+                (parity & 1) as f32
+            }
+            Pattern::Stripes { axis } => {
+                let parity = p.dot(&axis).floor() as i64;
+                (parity & 1) as f32
+            }
+            Pattern::Noise => 0.5 + 0.5 * noise::perlin(p),
+            Pattern::Fbm {
+                octaves,
+                lacunarity,
+                gain,
+            } => 0.5 + 0.5 * noise::fbm(p, octaves, lacunarity, gain),
+            Pattern::Turbulence {
+                octaves,
+                lacunarity,
+                gain,
+            } => noise::turbulence(p, octaves, lacunarity, gain),
+            Pattern::Marble { octaves, strength } => {
+                let phase = p.x + strength * noise::turbulence(p, octaves, 2.0, 0.5);
+                0.5 + 0.5 * (PI * phase).sin()
+            }
+            Pattern::Wood { rings, strength } => {
+                let radius = (p.x * p.x + p.z * p.z).sqrt();
+                let r = (radius + strength * noise::perlin(p)) * rings;
+                r - r.floor()
+            }
+            Pattern::Voronoi { jitter } => noise::voronoi(p, jitter),
+        }
+        .clamp(0.0, 1.0)
+    }
+}
+
+/// Blends two colours by a pattern evaluated at the hit position times
+/// `scale`.
+#[derive(Clone, Copy, Debug)]
+pub struct Procedural {
+    pub pattern: Pattern,
+    /// Colours at pattern values 0 and 1.
+    pub colors: [Vec3; 2],
+    pub scale: f32,
+    pub space: TextureSpace,
+}
+
+impl Procedural {
+    pub fn color(&self, hit: &Hit) -> Vec3 {
+        let p = match self.space {
+            TextureSpace::World => hit.point,
+            TextureSpace::Object => hit.local,
+        };
+        let t = self.pattern.eval(&(p * self.scale));
+        self.colors[0] * (1.0 - t) + self.colors[1] * t
+    }
+}
+
+/// How texture coordinates outside `[0, 1]` are brought back into the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    /// Repeats, flipping every other copy.
+    Mirror,
+    /// Extends the edge texels.
+    Clamp,
+}
+
+impl Wrap {
+    fn apply(self, i: isize, size: usize) -> usize {
+        let n = size as isize;
+        match self {
+            Wrap::Repeat => i.rem_euclid(n) as usize,
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * n);
+                (if i < n { i } else { 2 * n - 1 - i }) as usize
+            }
+            Wrap::Clamp => i.clamp(0, n - 1) as usize,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    /// Bilinear interpolation of the full-resolution image.
+    Bilinear,
+    /// Bilinear lookups in the two mip levels closest to the pixel footprint,
+    /// blended linearly.
+    Trilinear,
+}
+
+/// Image texture with a precomputed mip-map pyramid. Texture coordinate
+/// `(0, 0)` is the bottom left of the image.
+#[derive(Clone)]
+pub struct ImageTexture {
+    /// Full resolution first, each level half the size of the previous one.
+    levels: Vec<Image>,
+    pub wrap: Wrap,
+    pub filter: TextureFilter,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image, wrap: Wrap, filter: TextureFilter) -> ImageTexture {
+        let mut levels = vec![image];
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = downsample(last);
+            levels.push(next);
+        }
+        ImageTexture {
+            levels,
+            wrap,
+            filter,
+        }
+    }
+
+    /// Filtered colour at `uv` for a pixel footprint spanning `duvdx` and
+    /// `duvdy` in texture space.
+    pub fn lookup(&self, uv: &Vec2, duvdx: &Vec2, duvdy: &Vec2) -> Vec3 {
+        if self.filter == TextureFilter::Bilinear {
+            return self.bilinear(0, uv);
+        }
+        let base = &self.levels[0];
+        let size = Vec2::new(base.width as f32, base.height as f32);
+        let width = duvdx
+            .component_mul(&size)
+            .norm()
+            .max(duvdy.component_mul(&size).norm());
+        let max_level = (self.levels.len() - 1) as f32;
+        let level = width.max(1e-8).log2().clamp(0.0, max_level);
+        let lower = level.floor();
+        let t = level - lower;
+        let color = self.bilinear(lower as usize, uv);
+        if t > 0.0 {
+            color * (1.0 - t) + self.bilinear(lower as usize + 1, uv) * t
+        } else {
+            color
+        }
+    }
+
+    fn bilinear(&self, level: usize, uv: &Vec2) -> Vec3 {
+        let image = &self.levels[level];
+        let x = uv.x * image.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * image.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let texel = |x: isize, y: isize| {
+            image.get(
+                self.wrap.apply(x, image.width),
+                self.wrap.apply(y, image.height),
+            )
+        };
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1, y0) * fx;
+        let bottom = texel(x0, y0 + 1) * (1.0 - fx) + texel(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+/// Next mip level: each texel averages the 2x2 block it covers, with the
+/// last row or column of odd-sized images folded into their neighbours.
+fn downsample(image: &Image) -> Image {
+    let width = (image.width / 2).max(1);
+    let height = (image.height / 2).max(1);
+    let mut out = Image::new(width, height);
+    for y in 0..height {
+        let y0 = y * image.height / height;
+        let y1 = ((y + 1) * image.height / height).max(y0 + 1);
+        for x in 0..width {
+            let x0 = x * image.width / width;
+            let x1 = ((x + 1) * image.width / width).max(x0 + 1);
+            let mut sum = Vec3::new(0.0, 0.0, 0.0);
+            for sy in y0..y1 {
+                for sx in x0..x1 {
+                    sum += image.get(sx, sy);
+                }
+            }
+            out.set(x, y, sum / ((x1 - x0) * (y1 - y0)) as f32);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_modes() {
+        let indices: Vec<isize> = (-5..9).collect();
+        let wrapped =
+            |wrap: Wrap| -> Vec<usize> { indices.iter().map(|&i| wrap.apply(i, 4)).collect() };
+        assert_eq!(
+            wrapped(Wrap::Repeat),
+            [3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0]
+        );
+        assert_eq!(
+            wrapped(Wrap::Mirror),
+            [3, 3, 2, 1, 0, 0, 1, 2, 3, 3, 2, 1, 0, 0]
+        );
+        assert_eq!(
+            wrapped(Wrap::Clamp),
+            [0, 0, 0, 0, 0, 0, 1, 2, 3, 3, 3, 3, 3, 3]
+        );
+        assert_eq!(Wrap::Mirror.apply(0, 1), 0);
+        assert_eq!(Wrap::Repeat.apply(-1, 1), 0);
+    }
+
+    fn gradient(width: usize, height: usize) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set(x, y, Vec3::new(x as f32, y as f32, (x * y) as f32));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn downsample_averages_blocks() {
+        let half = downsample(&gradient(4, 2));
+        assert_eq!((half.width, half.height), (2, 1));
+        assert_eq!(half.get(0, 0), Vec3::new(0.5, 0.5, 0.25));
+        assert_eq!(half.get(1, 0), Vec3::new(2.5, 0.5, 1.25));
+        // Odd sizes fold the last column into the previous texel.
+        let odd = downsample(&gradient(3, 1));
+        assert_eq!((odd.width, odd.height), (1, 1));
+        assert_eq!(odd.get(0, 0), Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn mip_pyramid_ends_at_the_mean() {
+        let image = gradient(8, 2);
+        let mean = |image: &Image| image.pixels.iter().sum::<Vec3>() / image.pixels.len() as f32;
+        let texture = ImageTexture::new(image.clone(), Wrap::Repeat, TextureFilter::Trilinear);
+        let last = &texture.levels[texture.levels.len() - 1];
+        assert_eq!((last.width, last.height), (1, 1));
+        assert!((mean(last) - mean(&image)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn bilinear_hits_texel_centres() {
+        let texture = ImageTexture::new(gradient(4, 2), Wrap::Clamp, TextureFilter::Bilinear);
+        let zero = Vec2::new(0.0, 0.0);
+        // Texel (1, 0) is in the top row, which is v = 1.
+        let centre = Vec2::new(1.5 / 4.0, 0.75);
+        assert_eq!(
+            texture.lookup(&centre, &zero, &zero),
+            Vec3::new(1.0, 0.0, 0.0)
+        );
+        let between = Vec2::new(2.0 / 4.0, 0.5);
+        let value = texture.lookup(&between, &zero, &zero);
+        assert!(
+            (value - Vec3::new(1.5, 0.5, 0.75)).norm() < 1e-6,
+            "{:?}",
+            value
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::image::Image;
+use crate::sampling::Rng;
+use crate::Vec3;
+
+/// Luminance that `reinhard-extended` maps to white unless told otherwise.
+pub const DEFAULT_WHITE: f32 = 4.0;
+
+/// Curve compressing scene-referred radiance into the displayable range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapper {
+    /// No compression; values above 1 clip when quantised.
+    None,
+    /// `L / (1 + L)` on luminance.
+    Reinhard,
+    /// Reinhard with luminance `white` mapped to 1.
+    ReinhardExtended { white: f32 },
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's Uncharted 2 filmic curve.
+    Hable,
+    /// Troy Sobotka's AgX, in the common polynomial approximation.
+    Agx,
+}
+
+impl ToneMapper {
+    pub fn from_name(name: &str) -> Option<ToneMapper> {
+        match name {
+            "none" => Some(ToneMapper::None),
+            "reinhard" => Some(ToneMapper::Reinhard),
+            "reinhard-extended" => Some(ToneMapper::ReinhardExtended {
+                white: DEFAULT_WHITE,
+            }),
+            "aces" => Some(ToneMapper::Aces),
+            "hable" => Some(ToneMapper::Hable),
+            "agx" => Some(ToneMapper::Agx),
+            _ => None,
+        }
+    }
+
+    /// Maps a linear colour to linear display values, mostly within `[0, 1]`.
+    pub fn map(&self, c: Vec3) -> Vec3 {
+        match *self {
+            ToneMapper::None => c,
+            ToneMapper::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+            ToneMapper::ReinhardExtended { white } => {
+                scale_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapper::Aces => c.map(|x| {
+                let x = x.max(0.0);
+                (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }),
+            ToneMapper::Hable => {
+                const WHITE: f32 = 11.2;
+                let scale = 1.0 / hable(WHITE);
+                c.map(|x| hable(2.0 * x.max(0.0)) * scale)
+            }
+            ToneMapper::Agx => agx(c),
+        }
+    }
+}
+
+fn luminance(c: &Vec3) -> f32 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+fn scale_luminance<F: Fn(f32) -> f32>(c: Vec3, curve: F) -> Vec3 {
+    let l = luminance(&c);
+    if l <= 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    c * (curve(l) / l)
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+fn agx(c: Vec3) -> Vec3 {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+    let inset = na::Matrix3::new(
+        0.84247906,
+        0.0784336,
+        0.079223745,
+        0.042328242,
+        0.87846864,
+        0.07916613,
+        0.042375655,
+        0.0784336,
+        0.879143,
+    );
+    let outset = na::Matrix3::new(
+        1.196879,
+        -0.09802088,
+        -0.09902974,
+        -0.052896852,
+        1.1519031,
+        -0.098961177,
+        -0.052971636,
+        -0.09804345,
+        1.1510737,
+    );
+    let v: Vec3 = inset * c;
+    let v = v.map(|x| {
+        let x = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+    // The curve yields display-encoded values; undo the 2.2 gamma so every
+    // mapper hands linear values to the transfer function.
+    let v: Vec3 = outset * v;
+    v.map(|x| x.clamp(0.0, 1.0).powf(2.2))
+}
+
+/// Transfer function applied after tone mapping.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+    Linear,
+    Srgb,
+}
+
+fn srgb_encode(x: f32) -> f32 {
+    let x = x.clamp(0.0, 1.0);
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else if x < 1.0 {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    } else {
+        // The formula falls an ulp short of 1, which would quantise white to
+        // 254.
+        1.0
+    }
+}
+
+/// Post-process turning a linear framebuffer into display values for 8-bit
+/// output: exposure, tone curve, transfer function and optional dithering.
+/// The default only applies the sRGB transfer function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping {
+    /// Exposure adjustment in stops.
+    pub exposure: f32,
+    pub mapper: ToneMapper,
+    pub transfer: Transfer,
+    /// Adds up to one quantisation step of noise per pixel so that 8-bit
+    /// rounding does not produce banding in smooth gradients.
+    pub dither: bool,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 0.0,
+            mapper: ToneMapper::None,
+            transfer: Transfer::Srgb,
+            dither: false,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn apply(&self, image: &Image) -> Image {
+        let scale = 2f32.powf(self.exposure);
+        let mut out = image.clone();
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let mut c = self.mapper.map(image.get(x, y) * scale);
+                if self.transfer == Transfer::Srgb {
+                    c = c.map(srgb_encode);
+                }
+                if self.dither {
+                    // Quantisation truncates, so uniform noise in [0, 1) steps
+                    // rounds to the nearest level on average.
+                    let mut rng = Rng::for_pixel(x, y, 0);
+                    c = c.map(|v| v + rng.next_f32() / 255.0);
+                }
+                out.set(x, y, c);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPERS: [&str; 6] = [
+        "none",
+        "reinhard",
+        "reinhard-extended",
+        "aces",
+        "hable",
+        "agx",
+    ];
+
+    fn grey(mapper: &ToneMapper, v: f32) -> Vec3 {
+        mapper.map(Vec3::new(v, v, v))
+    }
+
+    fn assert_grey(c: Vec3, expected: f32, tolerance: f32) {
+        for v in c.iter() {
+            assert!((v - expected).abs() < tolerance, "{:?} != {}", c, expected);
+        }
+    }
+
+    #[test]
+    fn curves_at_zero_one_and_large_values() {
+        for name in &MAPPERS {
+            let mapper = ToneMapper::from_name(name).unwrap();
+            assert_grey(grey(&mapper, 0.0), 0.0, 1e-6);
+        }
+        let mapper = |name| ToneMapper::from_name(name).unwrap();
+        assert_grey(grey(&mapper("none"), 1.0), 1.0, 1e-6);
+        assert_grey(grey(&mapper("none"), 100.0), 100.0, 1e-6);
+        assert_grey(grey(&mapper("reinhard"), 1.0), 0.5, 1e-6);
+        assert_grey(grey(&mapper("reinhard"), 1e4), 1.0, 1e-3);
+        assert_grey(grey(&mapper("reinhard-extended"), 1.0), 0.53125, 1e-6);
+        assert_grey(grey(&mapper("reinhard-extended"), DEFAULT_WHITE), 1.0, 1e-6);
+        let white = ToneMapper::ReinhardExtended { white: 10.0 };
+        assert_grey(grey(&white, 10.0), 1.0, 1e-6);
+        assert_grey(grey(&mapper("aces"), 1.0), 2.54 / 3.16, 1e-6);
+        assert_grey(grey(&mapper("aces"), 1e4), 1.0, 1e-6);
+        assert_grey(grey(&mapper("hable"), 1.0), 0.4929, 1e-4);
+        // Hable's curve reaches white at its white point, 11.2 after the
+        // exposure bias of 2.
+        assert_grey(grey(&mapper("hable"), 5.6), 1.0, 1e-5);
+        let agx = grey(&mapper("agx"), 1.0);
+        assert!(agx.iter().all(|v| *v > 0.2 && *v < 0.8), "{:?}", agx);
+        assert_grey(grey(&mapper("agx"), 1e4), 1.0, 0.05);
+    }
+
+    #[test]
+    fn curves_are_monotonic() {
+        for name in &MAPPERS {
+            let mapper = ToneMapper::from_name(name).unwrap();
+            let mut last = -1.0;
+            for k in 0..=64 {
+                let v = grey(&mapper, k as f32 / 4.0).x;
+                assert!(v >= last, "{} at {}", name, k as f32 / 4.0);
+                last = v;
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_encode_inverts_decoding() {
+        let mut image = Image::new(256, 1);
+        for x in 0..256 {
+            let v = x as f32 / 255.0;
+            image.set(x, 0, Vec3::new(v, v, v));
+        }
+        let round_trip = ToneMapping::default().apply(&image.srgb_to_linear());
+        for (p, q) in image.pixels.iter().zip(&round_trip.pixels) {
+            assert!((p - q).norm() < 1e-5, "{:?} != {:?}", p, q);
+        }
+        // The linear segment meets the power curve without a jump.
+        let knee = 0.003_130_8;
+        assert!((srgb_encode(knee - 1e-7) - srgb_encode(knee + 1e-7)).abs() < 1e-5);
+        assert_eq!(srgb_encode(-1.0), 0.0);
+        assert_eq!(srgb_encode(2.0), 1.0);
+    }
+
+    #[test]
+    fn exposure_scales_before_the_curve() {
+        let mut image = Image::new(1, 1);
+        image.set(0, 0, Vec3::new(0.25, 0.5, 1.0));
+        let mapping = ToneMapping {
+            exposure: 1.0,
+            transfer: Transfer::Linear,
+            ..ToneMapping::default()
+        };
+        assert_eq!(mapping.apply(&image).get(0, 0), Vec3::new(0.5, 1.0, 2.0));
+    }
+}